@@ -1,13 +1,12 @@
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
-use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, ToTokens};
 use syn::{
     parse::{Parse, ParseStream, Result},
     parse_macro_input,
     punctuated::Punctuated,
-    DeriveInput, Fields, Ident, ItemStruct, Token, Type, TypePath,
+    Data, DeriveInput, Error, Expr, Fields, Ident, Token, Type, TypePath,
 };
 
 #[proc_macro_derive(OsuEncode)]
@@ -29,21 +28,19 @@ pub fn try_from_encode(input: TokenStream) -> TokenStream {
     TokenStream::from(quote! {
         impl OsuEncode for #ident {
             fn encoded_size(&self) -> usize {
-                std::mem::size_of_value(self)
+                std::mem::size_of::< #ty >()
             }
 
             fn encode(&self, buf: &mut Vec<u8>) {
                 (*self as #ty ).encode(buf);
             }
+        }
 
-            fn decode(buf: &[u8]) -> Result<(&Self, usize), ()> {
-                let (&val, off) = < #ty >::decode(buf)?;
+        impl<'a> OsuDecode<'a> for #ident {
+            fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+                let val: #ty = reader.read()?;
                 use std::convert::TryFrom;
-                Self::try_from(val)?;
-                Ok((
-                    unsafe { &*(buf.as_ptr() as *const Self) },
-                    off
-                ))
+                Self::try_from(val).map_err(|_| DecodeError::InvalidValue)
             }
         }
     })
@@ -67,119 +64,95 @@ impl Parse for Decoder {
     }
 }
 
-enum TType {
-    Owned(TokenStream2),
-    Borrowed(TokenStream2),
-}
-
-#[proc_macro_attribute]
-pub fn event_data(_args: TokenStream, input: TokenStream) -> TokenStream {
-    let parsed = parse_macro_input!(input as ItemStruct);
-    let redefined = redefine_struct(parsed);
-
-    TokenStream::from(redefined)
-}
+/// Generates `OsuEncode` and `OsuDecode` for a struct, encoding the fields in order.
+/// With `#[packet(Id::Something)]` it also implements `OsuPacket`,
+/// and a field's decoding can be overridden with `#[decoder(func: earlier, fields)]`
+#[proc_macro_derive(OsuPacket, attributes(packet, decoder))]
+pub fn osu_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(|f| {
+                    let decoder = match f.attrs.iter().find(|a| a.path.is_ident("decoder")) {
+                        Some(a) => Some(a.parse_args::<Decoder>()?),
+                        None => None,
+                    };
+                    Ok((f.ident.as_ref().unwrap().clone(), f.ty.clone(), decoder))
+                })
+                .collect::<Result<Vec<(Ident, Type, Option<Decoder>)>>>(),
+            Fields::Unit => Ok(Vec::new()),
+            Fields::Unnamed(fields) => {
+                Err(Error::new_spanned(fields, "tuple structs can't be packets"))
+            }
+        },
+        _ => Err(Error::new_spanned(name, "only structs can be packets")),
+    };
+    let fields = match fields {
+        Ok(fields) => fields,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    // Borrowed fields have to live as long as the buffer they're decoded from
+    let (decode_generics, lifetime) = match input.generics.lifetimes().next() {
+        Some(l) => (quote!(#impl_generics), l.lifetime.to_token_stream()),
+        None => (quote!(<'de>), quote!('de)),
+    };
 
-fn type_to_tt(ty: &Type) -> TType {
-    match ty {
-        Type::Reference(t) => TType::Borrowed(t.elem.to_token_stream()),
-        Type::Path(t) => TType::Owned({
-            // let ty = f.ty.clone();
-            let seg = t.path.segments.first().unwrap();
-            use syn::PathArguments as PA;
-            match &seg.arguments {
-                PA::None => ty.to_token_stream(),
-                PA::AngleBracketed(arg) => {
-                    use syn::GenericArgument as GA;
-                    let args = arg
-                        .args
-                        .iter()
-                        .filter_map(|a| match a {
-                            GA::Type(a) => Some(type_to_tt(a)),
-                            _ => None,
-                        })
-                        .map(|a| match a {
-                            TType::Borrowed(stream) => quote!(&'a #stream),
-                            TType::Owned(stream) => stream,
-                        });
-                    let t = &seg.ident;
-                    quote!(#t < #(#args),* > )
+    let idents = fields.iter().map(|(ident, ..)| ident).collect::<Vec<_>>();
+    let reads = fields.iter().map(|(ident, ty, decoder)| {
+        let decoder = match decoder {
+            Some(d) => {
+                let func = &d.name;
+                let args = d.args.iter().map(|arg| quote!(&#arg));
+                quote!(#func(reader #(, #args)*))
+            }
+            None => quote!(reader.read()),
+        };
+        quote!(let #ident: #ty = #decoder?;)
+    });
+    let id = input
+        .attrs
+        .iter()
+        .find(|a| a.path.is_ident("packet"))
+        .map(|a| a.parse_args::<Expr>())
+        .transpose();
+    let packet = match id {
+        Ok(id) => id.map(|id| {
+            quote! {
+                impl #impl_generics crate::packets::OsuPacket for #name #ty_generics #where_clause {
+                    const ID: crate::packets::Id = #id;
                 }
-                _ => unreachable!(),
             }
-            // dbg!(&f.ty).to_token_stream()
         }),
-        Type::Array(arr) => TType::Owned(arr.to_token_stream()),
-        e => {
-            println!("----------------\n{:#?}\n-----------------", e);
-            unreachable!()
-        }
-    }
-}
-
-fn redefine_struct(input: ItemStruct) -> TokenStream2 {
-    let fields = match input.fields {
-        Fields::Named(a) => a
-            .named
-            .iter()
-            .map(|f| {
-                (
-                    f.ident.as_ref().unwrap().clone(),
-                    type_to_tt(&f.ty),
-                    f.attrs
-                        .iter()
-                        .find(|a| a.path.is_ident("decoder"))
-                        .map(|a| a.parse_args::<Decoder>().unwrap()),
-                )
-            })
-            .collect::<Vec<(Ident, TType, Option<Decoder>)>>(),
-        _ => unimplemented!(),
+        Err(e) => return e.to_compile_error().into(),
     };
-    let name = &input.ident;
-    let attrs = &input.attrs;
-    let fields_parsed = fields.iter().map(|(ident, t, _)| {
-        let t = match t {
-            TType::Owned(t) => t.clone(),
-            TType::Borrowed(t) => quote!(&'a #t),
-        };
-        quote!(#ident : #t)
-    });
-    let fields_idents = fields.iter().map(|(ident, ..)| ident);
-    let fields_impl = fields.iter().map(|(ident, t, f)| {
-        let decoder = f.as_ref().map(|i| {
-            let name = &i.name;
-            let args = i.args.iter().map(|arg| quote!(&#arg));
-            quote!(#name(data #(, #args)*))
-        });
-        let name = match decoder {
-            None => match t {
-                TType::Owned(_) => quote!(& #ident),
-                TType::Borrowed(_) => ident.to_token_stream(),
-            },
-            Some(_) => ident.to_token_stream(),
-        };
-        let t = match t {
-            TType::Owned(t) | TType::Borrowed(t) => t,
-        };
-        let decoder = decoder.unwrap_or_else(|| quote!(<#t as OsuEncode>::decode(data)));
-        quote! {
-            let data = &data[off..];
-            let (#name, off) = #decoder?;
-        }
-    });
-    quote! {
-        #(#attrs)*
-        struct #name <'a> {
-            #(#fields_parsed),*
+
+    TokenStream::from(quote! {
+        impl #impl_generics crate::packets::OsuEncode for #name #ty_generics #where_clause {
+            fn encoded_size(&self) -> usize {
+                0 #(+ crate::packets::OsuEncode::encoded_size(&self.#idents))*
+            }
+
+            fn encode(&self, buf: &mut Vec<u8>) {
+                #(crate::packets::OsuEncode::encode(&self.#idents, buf);)*
+            }
         }
 
-        impl <'a> #name <'a> {
-            fn decode(data: &'a [u8]) -> Result<#name, ()> {
-                use crate::packets::OsuEncode;
-                let off = 0;
-                #(#fields_impl)*
-                Ok(#name { #(#fields_idents),* })
+        impl #decode_generics crate::packets::OsuDecode<#lifetime>
+            for #name #ty_generics #where_clause
+        {
+            fn read(
+                reader: &mut crate::packets::Reader<#lifetime>,
+            ) -> Result<Self, crate::packets::DecodeError> {
+                #(#reads)*
+                Ok(#name { #(#idents),* })
             }
         }
-    }
+
+        #packet
+    })
 }
@@ -0,0 +1,79 @@
+-- Tables isoku needs on top of the ones shared with Uncho
+
+CREATE TABLE IF NOT EXISTS friends (
+    user_id INTEGER NOT NULL,
+    friend_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, friend_id)
+);
+
+CREATE TABLE IF NOT EXISTS user_stats (
+    user_id INTEGER NOT NULL,
+    game_mode SMALLINT NOT NULL,
+    ranked_score BIGINT NOT NULL DEFAULT 0,
+    total_score BIGINT NOT NULL DEFAULT 0,
+    accuracy REAL NOT NULL DEFAULT 0,
+    playcount INTEGER NOT NULL DEFAULT 0,
+    pp INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, game_mode)
+);
+
+CREATE TABLE IF NOT EXISTS silences (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    issuer_id INTEGER NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS silences_user_id ON silences (user_id);
+
+CREATE TABLE IF NOT EXISTS user_privileges (
+    user_id INTEGER PRIMARY KEY,
+    privileges INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id SERIAL PRIMARY KEY,
+    actor_id INTEGER NOT NULL,
+    target_id INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id SERIAL PRIMARY KEY,
+    sender_id INTEGER NOT NULL,
+    sender_name TEXT NOT NULL,
+    target TEXT NOT NULL,
+    recipient_id INTEGER,
+    content TEXT NOT NULL,
+    delivered BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS messages_undelivered ON messages (recipient_id) WHERE NOT delivered;
+CREATE INDEX IF NOT EXISTS messages_undelivered_sender ON messages (sender_id) WHERE NOT delivered;
+
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id INTEGER PRIMARY KEY,
+    block_non_friends BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE IF NOT EXISTS channels (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT '',
+    auto_join BOOLEAN NOT NULL DEFAULT FALSE,
+    read_privileges INTEGER NOT NULL DEFAULT 1,
+    write_privileges INTEGER NOT NULL DEFAULT 1
+);
+
+INSERT INTO channels (name, description, auto_join) VALUES
+    ('#osu', 'wojexe to ciota', TRUE),
+    ('#lobby', 'multi', FALSE)
+ON CONFLICT DO NOTHING;
+
+INSERT INTO channels (name, description, auto_join, write_privileges) VALUES
+    ('#announce', 'Announcements', TRUE, 8)
+ON CONFLICT DO NOTHING;
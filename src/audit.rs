@@ -0,0 +1,23 @@
+use crate::{events::silence::now, Glob};
+
+/// Records a moderation action in the audit log
+pub async fn log(
+    actor: i32,
+    target: i32,
+    action: &str,
+    reason: &str,
+    glob: &Glob,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_log (actor_id, target_id, action, reason, created_at)
+        VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(actor)
+    .bind(target)
+    .bind(action)
+    .bind(reason)
+    .bind(now() as i64)
+    .execute(&glob.db_pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use super::command::{Args, Command, CommandResult, Context};
+use crate::{channel::ChannelDef, events::channels, privileges::Privileges};
+use async_trait::async_trait;
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "yes" | "true" | "1" | "on" => Some(true),
+        "no" | "false" | "0" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+pub struct AddChannel;
+
+#[async_trait]
+impl Command for AddChannel {
+    fn name(&self) -> &'static str { "addchannel" }
+
+    fn usage(&self) -> &'static str { "<name> <read> <write> <autojoin> [description]" }
+
+    fn description(&self) -> &'static str {
+        "Creates a channel, privileges look like normal, supporter, moderator or admin"
+    }
+
+    fn privileges(&self) -> Privileges { Privileges::ADMIN }
+
+    async fn run(&self, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        let def = ChannelDef {
+            name: args.next("name")?.to_string(),
+            read_privileges: args.parse("read")?,
+            write_privileges: args.parse("write")?,
+            auto_join: args.parse_with("autojoin", parse_bool)?,
+            desc: args.rest(),
+        };
+        let name = def.name.clone();
+        channels::create(def, ctx.glob).await?;
+        Ok(Some(format!("{} has been created", name)))
+    }
+}
+
+pub struct RemoveChannel;
+
+#[async_trait]
+impl Command for RemoveChannel {
+    fn name(&self) -> &'static str { "removechannel" }
+
+    fn usage(&self) -> &'static str { "<name>" }
+
+    fn description(&self) -> &'static str { "Deletes the channel and kicks everyone out of it" }
+
+    fn privileges(&self) -> Privileges { Privileges::ADMIN }
+
+    async fn run(&self, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        let name = args.next("name")?;
+        channels::remove(name, ctx.glob).await?;
+        Ok(Some(format!("{} has been removed", name)))
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{events::EventError, privileges::Privileges, Glob, Token};
+use async_trait::async_trait;
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// What the command was invoked by
+pub struct Context<'a> {
+    pub token: &'a Arc<dyn Token>,
+    pub glob: &'a Glob,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgError {
+    Missing(&'static str),
+    Invalid { name: &'static str, value: String },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::Missing(name) => write!(f, "Missing argument <{}>", name),
+            ArgError::Invalid { name, value } => {
+                write!(f, "\"{}\" is not a valid <{}>", value, name)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Args(ArgError),
+    Event(EventError),
+}
+
+impl From<ArgError> for CommandError {
+    fn from(e: ArgError) -> Self { CommandError::Args(e) }
+}
+
+impl From<EventError> for CommandError {
+    fn from(e: EventError) -> Self { CommandError::Event(e) }
+}
+
+/// On success the command can reply to whoever invoked it
+pub type CommandResult = Result<Option<String>, CommandError>;
+
+/// Arguments of a command, split on whitespace
+pub struct Args<'a> {
+    args: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a str) -> Self {
+        Args {
+            args: args.split_whitespace().collect(),
+            pos: 0,
+        }
+    }
+
+    pub fn next(&mut self, name: &'static str) -> Result<&'a str, ArgError> {
+        let res = *self.args.get(self.pos).ok_or(ArgError::Missing(name))?;
+        self.pos += 1;
+        Ok(res)
+    }
+
+    pub fn parse<T: FromStr>(&mut self, name: &'static str) -> Result<T, ArgError> {
+        let val = self.next(name)?;
+        val.parse().map_err(|_| ArgError::Invalid {
+            name,
+            value: val.to_string(),
+        })
+    }
+
+    /// Parses the argument with a custom function, for values that aren't `FromStr`
+    pub fn parse_with<T>(
+        &mut self,
+        name: &'static str,
+        f: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, ArgError> {
+        let val = self.next(name)?;
+        f(val).ok_or_else(|| ArgError::Invalid {
+            name,
+            value: val.to_string(),
+        })
+    }
+
+    pub fn optional<T: FromStr>(&mut self, name: &'static str) -> Result<Option<T>, ArgError> {
+        if self.pos >= self.args.len() {
+            return Ok(None);
+        }
+        self.parse(name).map(Some)
+    }
+
+    /// All of the remaining arguments joined back together
+    pub fn rest(&mut self) -> String {
+        let res = self.args[self.pos..].join(" ");
+        self.pos = self.args.len();
+        res
+    }
+}
+
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn aliases(&self) -> &'static [&'static str] { &[] }
+    /// Arguments in the form of `<required> [optional]`
+    fn usage(&self) -> &'static str { "" }
+    fn description(&self) -> &'static str;
+    fn privileges(&self) -> Privileges { Privileges::default() }
+    async fn run(&self, args: Args<'_>, ctx: &Context<'_>) -> CommandResult;
+}
+
+/// Every command the bot knows about
+#[derive(Default)]
+pub struct Commands {
+    list: Vec<Box<dyn Command>>,
+}
+
+impl Commands {
+    pub fn register<C: Command + 'static>(&mut self, command: C) -> &mut Self {
+        self.list.push(Box::new(command));
+        self
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn Command> {
+        self.list
+            .iter()
+            .find(|c| c.name() == name || c.aliases().iter().any(|&a| a == name))
+            .map(|c| c.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Command> {
+        self.list.iter().map(|c| c.as_ref())
+    }
+
+    /// Help listing only the commands the user is allowed to use
+    pub fn help(&self, privileges: Privileges) -> String {
+        self.iter()
+            .filter(|c| privileges.has(c.privileges()))
+            .map(|c| {
+                let usage = if c.usage().is_empty() {
+                    String::new()
+                } else {
+                    format!(" {}", c.usage())
+                };
+                format!("!{}{} - {}", c.name(), usage, c.description())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(|c| c.name())).finish()
+    }
+}
@@ -0,0 +1,32 @@
+use super::command::{Args, Command, CommandResult, Context};
+use async_trait::async_trait;
+
+pub struct Echo;
+
+#[async_trait]
+impl Command for Echo {
+    fn name(&self) -> &'static str { "echo" }
+
+    fn usage(&self) -> &'static str { "<text>" }
+
+    fn description(&self) -> &'static str { "Repeats what you said" }
+
+    async fn run(&self, mut args: Args<'_>, _ctx: &Context<'_>) -> CommandResult {
+        Ok(Some(args.rest()))
+    }
+}
+
+pub struct Help;
+
+#[async_trait]
+impl Command for Help {
+    fn name(&self) -> &'static str { "help" }
+
+    fn aliases(&self) -> &'static [&'static str] { &["h", "commands"] }
+
+    fn description(&self) -> &'static str { "Lists the commands you can use" }
+
+    async fn run(&self, _args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        Ok(Some(ctx.glob.commands.help(ctx.token.privileges())))
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    channel::HistoryEntry,
+    events::{EventError, EventResult},
+    packets::server::send_message as message_packet,
+    Glob, Token,
+};
+use std::sync::Arc;
+
+pub mod admin;
+pub mod command;
+pub use command::{ArgError, Args, Command, CommandError, CommandResult, Commands, Context};
+pub mod general;
+pub mod moderation;
+pub mod multiplayer;
+
+impl Commands {
+    /// Commands which come with the bot
+    pub fn builtin() -> Self {
+        let mut res = Commands::default();
+        res.register(general::Help)
+            .register(general::Echo)
+            .register(moderation::Kick)
+            .register(moderation::Silence)
+            .register(moderation::Unsilence)
+            .register(multiplayer::Mp)
+            .register(admin::AddChannel)
+            .register(admin::RemoveChannel);
+        res
+    }
+}
+
+pub async fn handle_command(cmd: &str, token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let (name, args) = match cmd.find(char::is_whitespace) {
+        Some(i) => (&cmd[..i], &cmd[i..]),
+        None => (cmd, ""),
+    };
+    let command = glob.commands.find(name).ok_or_else(|| {
+        EventError::not_found(format!("No such command \"{}\"! Try: !help", name))
+    })?;
+    if !token.privileges().has(command.privileges()) {
+        return Err(EventError::permission("You don't have permission to do that"));
+    }
+    let ctx = Context { token, glob };
+    let reply = command.run(Args::new(args), &ctx).await.map_err(|e| match e {
+        CommandError::Args(e) => EventError::invalid(format!(
+            "{}\nUsage: !{} {}",
+            e,
+            command.name(),
+            command.usage()
+        )),
+        CommandError::Event(e) => e,
+    })?;
+    if let Some(reply) = reply {
+        token
+            .enqueue_vec(message_packet(glob.bot.as_ref(), token.username(), &reply))
+            .await;
+    }
+    Ok(())
+}
+
+pub async fn send_message(content: &str, channel: &str, glob: &Glob) -> EventResult {
+    let channel = glob
+        .channel_list
+        .read()
+        .await
+        .get(channel)
+        .ok_or_else(|| EventError::not_found(format!("No channel named {}", channel)))?
+        .clone();
+    let packet = message_packet(glob.bot.as_ref(), channel.name(), content);
+    for c in channel.users.read().await.iter() {
+        c.enqueue(&packet).await;
+    }
+    channel
+        .push_history(HistoryEntry {
+            from: glob.bot.username().to_string(),
+            from_id: glob.bot.id(),
+            content: content.to_string(),
+        })
+        .await;
+    Ok(())
+}
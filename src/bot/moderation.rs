@@ -0,0 +1,72 @@
+use super::command::{Args, Command, CommandResult, Context};
+use crate::{
+    events::{kick, silence},
+    privileges::Privileges,
+};
+use async_trait::async_trait;
+
+pub struct Kick;
+
+#[async_trait]
+impl Command for Kick {
+    fn name(&self) -> &'static str { "kick" }
+
+    fn usage(&self) -> &'static str { "<user> [reason]" }
+
+    fn description(&self) -> &'static str { "Disconnects the user" }
+
+    fn privileges(&self) -> Privileges { Privileges::MODERATOR }
+
+    async fn run(&self, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        let user = args.next("user")?;
+        let reason = args.rest();
+        kick::handle(user, &reason, ctx.token, ctx.glob).await?;
+        Ok(Some(format!("{} has been kicked", user)))
+    }
+}
+
+pub struct Silence;
+
+#[async_trait]
+impl Command for Silence {
+    fn name(&self) -> &'static str { "silence" }
+
+    fn aliases(&self) -> &'static [&'static str] { &["mute"] }
+
+    fn usage(&self) -> &'static str { "<user> <duration> [reason]" }
+
+    fn description(&self) -> &'static str {
+        "Stops the user from chatting, duration looks like 30s, 10m, 2h or 1d"
+    }
+
+    fn privileges(&self) -> Privileges { Privileges::MODERATOR }
+
+    async fn run(&self, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        let user = args.next("user")?;
+        let seconds = args.parse_with("duration", silence::parse_duration)?;
+        let reason = args.rest();
+        silence::silence(user, seconds, &reason, ctx.token, ctx.glob).await?;
+        Ok(Some(format!("{} has been silenced for {} seconds", user, seconds)))
+    }
+}
+
+pub struct Unsilence;
+
+#[async_trait]
+impl Command for Unsilence {
+    fn name(&self) -> &'static str { "unsilence" }
+
+    fn aliases(&self) -> &'static [&'static str] { &["unmute"] }
+
+    fn usage(&self) -> &'static str { "<user>" }
+
+    fn description(&self) -> &'static str { "Ends the user's silence early" }
+
+    fn privileges(&self) -> Privileges { Privileges::MODERATOR }
+
+    async fn run(&self, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        let user = args.next("user")?;
+        silence::unsilence(user, ctx.token, ctx.glob).await?;
+        Ok(Some(format!("{} is no longer silenced", user)))
+    }
+}
@@ -0,0 +1,368 @@
+use super::{
+    command::{ArgError, Args, Command, CommandResult, Context},
+    send_message,
+};
+use crate::{
+    events::{
+        matches::{begin, broadcast, broadcast_lobby, dispose, leave, send_invite, update},
+        EventError,
+    },
+    packets::server::{
+        channel_join_success, create_match, dispose_match, match_change_password, match_complete,
+        match_transfer_host,
+    },
+    privileges::Privileges,
+    r#match::{ScoringType, Team, TeamType},
+    Channel, Match,
+};
+use async_trait::async_trait;
+use std::{
+    convert::TryFrom,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use tracing::debug;
+
+const MODS: &[(&str, u32)] = &[
+    ("NF", 1),
+    ("EZ", 2),
+    ("TD", 4),
+    ("HD", 8),
+    ("HR", 16),
+    ("SD", 32),
+    ("DT", 64),
+    ("RX", 128),
+    ("HT", 256),
+    ("NC", 512 | 64),
+    ("FL", 1024),
+    ("SO", 4096),
+    ("AP", 8192),
+    ("PF", 16384 | 32),
+];
+
+/// Parses either the numeric value of the mods or their acronyms,
+/// `FM` turns on freemod
+pub fn parse_mods(args: &str) -> Option<(u32, bool)> {
+    if let Ok(mods) = args.trim().parse() {
+        return Some((mods, false));
+    }
+    let mut mods = 0;
+    let mut freemod = false;
+    for arg in args.split_whitespace() {
+        let arg = arg.to_uppercase();
+        match arg.as_str() {
+            "FM" | "FREEMOD" => freemod = true,
+            "NM" | "NOMOD" => (),
+            _ => mods |= MODS.iter().find(|(name, _)| *name == arg)?.1,
+        }
+    }
+    Some((mods, freemod))
+}
+
+/// The match the user referees, preferring the one they're playing in
+async fn referee_match(ctx: &Context<'_>) -> Result<Arc<Match>, EventError> {
+    let id = ctx.token.id();
+    if let Some(player) = ctx.token.as_player() {
+        let own = player.multi.lock().await.as_ref().and_then(|m| m.upgrade());
+        if let Some(own) = own {
+            if own.is_referee(id).await {
+                return Ok(own);
+            }
+        }
+    }
+    let list: Vec<Arc<Match>> = ctx.glob.match_list.read().await.values().cloned().collect();
+    let mut res: Option<Arc<Match>> = None;
+    for m in list {
+        if m.is_referee(id).await && res.as_ref().map_or(true, |r| r.id < m.id) {
+            res = Some(m);
+        }
+    }
+    res.ok_or_else(|| EventError::permission("You aren't a referee of any match"))
+}
+
+async fn player_slot(multi: &Match, username: &str) -> Result<usize, EventError> {
+    multi
+        .slot_of_name(username)
+        .await
+        .ok_or_else(|| EventError::not_found(format!("{} isn't in the match", username)))
+}
+
+async fn user_id(username: &str, ctx: &Context<'_>) -> Result<i32, EventError> {
+    ctx.glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.username() == username)
+        .map(|t| t.id())
+        .ok_or_else(|| EventError::not_found(format!("{} is not online", username)))
+}
+
+/// Slot numbers are 1-based for the referees
+fn parse_slot(slot: &str) -> Option<usize> {
+    match slot.parse::<usize>() {
+        Ok(slot) if slot >= 1 && slot <= 16 => Some(slot - 1),
+        _ => None,
+    }
+}
+
+async fn make(mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+    // Everyone can referee a match they were added to, but match ids are limited
+    if !ctx.token.privileges().has(Privileges::MODERATOR) {
+        return Err(EventError::permission("Only moderators can make matches").into());
+    }
+    let name = args.rest();
+    if name.is_empty() {
+        return Err(ArgError::Missing("name").into());
+    }
+    let multi = {
+        let mut list = ctx.glob.match_list.write().await;
+        Match::new_empty(&mut list, &name)
+    }
+    .ok_or_else(|| EventError::internal("There are too many matches"))?;
+    multi.referees.write().await.push(ctx.token.id());
+    let ch = {
+        let mut list = ctx.glob.channel_list.write().await;
+        Channel::new(&mut list, &multi.channel_name(), "", false)
+    };
+    if ch.user_join(ctx.token.clone()).await {
+        ctx.token.join_channel(Arc::downgrade(&ch)).await;
+        ctx.token.enqueue_vec(channel_join_success(&ch)).await;
+    }
+    broadcast_lobby(ctx.glob, &create_match(&multi).await).await;
+    Ok(Some(format!("Created the match \"{}\" with id {}", name, multi.id)))
+}
+
+async fn invite(multi: &Match, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+    let username = args.next("user")?;
+    let target = ctx
+        .glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.username() == username)
+        .cloned()
+        .ok_or_else(|| EventError::not_found(format!("{} is not online", username)))?;
+    send_invite(multi, ctx.token.as_ref(), target.as_ref(), ctx.glob).await;
+    Ok(Some(format!("Invited {}", username)))
+}
+
+fn set(multi: &Match, mut args: Args<'_>) -> Result<(), ArgError> {
+    let team_type =
+        args.parse_with("teammode", |s| TeamType::try_from(s.parse::<u8>().ok()?).ok())?;
+    let scoring_type = args.optional::<u8>("scoremode")?;
+    let size = args.optional::<usize>("size")?;
+    multi.team_type.store(team_type as u8, Ordering::SeqCst);
+    let teams = team_type == TeamType::TeamVs || team_type == TeamType::TagTeamVs;
+    for slot in multi.slots.iter() {
+        let team = slot.team.load(Ordering::SeqCst);
+        if !teams {
+            slot.team.store(Team::NoTeam as u8, Ordering::SeqCst);
+        } else if team == Team::NoTeam as u8 {
+            slot.team.store(Team::Red as u8, Ordering::SeqCst);
+        }
+    }
+    if let Some(scoring_type) = scoring_type {
+        let scoring_type = ScoringType::try_from(scoring_type).map_err(|_| ArgError::Invalid {
+            name: "scoremode",
+            value: scoring_type.to_string(),
+        })?;
+        multi.scoring_type.store(scoring_type as u8, Ordering::SeqCst);
+    }
+    if let Some(size) = size {
+        multi.resize(size.min(16));
+    }
+    Ok(())
+}
+
+async fn start(multi: &Arc<Match>, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+    let seconds = args.optional::<u64>("countdown")?.unwrap_or(0);
+    if multi.in_progress.load(Ordering::SeqCst) {
+        return Err(EventError::invalid("The match is already in progress").into());
+    }
+    if seconds == 0 {
+        begin(multi, &ctx.glob.lobby).await?;
+        return Ok(None);
+    }
+    let countdown = multi.countdown.fetch_add(1, Ordering::SeqCst) + 1;
+    let channel = multi.channel_name();
+    let multi = multi.clone();
+    let lobby = ctx.glob.lobby.clone();
+    tokio::spawn(async move {
+        tokio::time::delay_for(Duration::from_secs(seconds)).await;
+        // Aborted or restarted in the meantime
+        if multi.countdown.load(Ordering::SeqCst) != countdown {
+            return;
+        }
+        if let Err(e) = begin(&multi, &lobby).await {
+            debug!(%e, id = multi.id, "couldn't start the match after countdown");
+        }
+    });
+    let msg = format!("The match starts in {} seconds", seconds);
+    send_message(&msg, &channel, ctx.glob).await?;
+    Ok(None)
+}
+
+async fn close(multi: &Arc<Match>, ctx: &Context<'_>) -> CommandResult {
+    multi.countdown.fetch_add(1, Ordering::SeqCst);
+    multi.referees.write().await.clear();
+    for slot in multi.slots.iter() {
+        let token = slot.token.read().await.clone();
+        if let Some(token) = token {
+            if let Some(player) = token.as_player() {
+                *player.multi.lock().await = None;
+            }
+            // Takes the client back to the lobby
+            token.enqueue_vec(dispose_match(multi.id)).await;
+            leave(multi, &token, ctx.glob).await;
+        }
+    }
+    // The last player leaving already disposed of it
+    dispose(multi, ctx.glob).await;
+    Ok(Some(format!("Closed the match {}", multi.id)))
+}
+
+pub struct Mp;
+
+#[async_trait]
+impl Command for Mp {
+    fn name(&self) -> &'static str { "mp" }
+
+    fn usage(&self) -> &'static str {
+        "<make|invite|lock|unlock|size|set|move|host|map|mods|start|abort|team|password|addref\
+         |removeref|close> [args]"
+    }
+
+    fn description(&self) -> &'static str { "Referee commands for multiplayer matches" }
+
+    async fn run(&self, mut args: Args<'_>, ctx: &Context<'_>) -> CommandResult {
+        let sub = args.next("command")?;
+        if sub == "make" {
+            return make(args, ctx).await;
+        }
+        let multi = referee_match(ctx).await?;
+        let reply = match sub {
+            "invite" => return invite(&multi, args, ctx).await,
+            "close" => return close(&multi, ctx).await,
+            "start" => return start(&multi, args, ctx).await,
+            "lock" => {
+                multi.set_locked(true);
+                "Locked the free slots".to_string()
+            }
+            "unlock" => {
+                multi.set_locked(false);
+                "Unlocked the slots".to_string()
+            }
+            "size" => {
+                let size = args.parse_with("size", |s| parse_slot(s).map(|s| s + 1))?;
+                multi.resize(size);
+                format!("Changed the match size to {}", size)
+            }
+            "set" => {
+                set(&multi, args)?;
+                "Changed the match settings".to_string()
+            }
+            "move" => {
+                let username = args.next("user")?;
+                let to = args.parse_with("slot", parse_slot)?;
+                let from = player_slot(&multi, username).await?;
+                if !multi.move_player(from, to).await {
+                    return Err(EventError::invalid(format!("Slot {} isn't free", to + 1)).into());
+                }
+                format!("Moved {} to slot {}", username, to + 1)
+            }
+            "host" => {
+                let username = args.next("user")?;
+                let slot = player_slot(&multi, username).await?;
+                let host = multi.slots[slot].token.read().await.clone();
+                if let Some(host) = host {
+                    *multi.host_id.write().await = host.id();
+                    host.enqueue_vec(match_transfer_host()).await;
+                }
+                format!("Made {} the host", username)
+            }
+            "map" => {
+                let beatmap_id = args.parse::<u32>("beatmap id")?;
+                let game_mode = args.optional::<u8>("mode")?;
+                if let Some(mode) = game_mode {
+                    if mode > 3 {
+                        return Err(ArgError::Invalid {
+                            name: "mode",
+                            value: mode.to_string(),
+                        }
+                        .into());
+                    }
+                    multi.game_mode.store(mode, Ordering::SeqCst);
+                }
+                multi.beatmap_id.store(beatmap_id, Ordering::SeqCst);
+                // The clients look the map up by its id when they don't know the hash
+                *multi.beatmap_name.write().await = String::new();
+                *multi.beatmap_md5.write().await = String::new();
+                multi.unready_all();
+                format!("Changed the beatmap to {}", beatmap_id)
+            }
+            "mods" => {
+                let (mods, freemod) = parse_mods(&args.rest())
+                    .ok_or_else(|| EventError::invalid("Unknown mods, try HD HR DT or FM"))?;
+                multi.mods.store(mods, Ordering::SeqCst);
+                multi.freemod.store(freemod, Ordering::SeqCst);
+                multi.unready_all();
+                "Changed the mods".to_string()
+            }
+            "abort" => {
+                multi.countdown.fetch_add(1, Ordering::SeqCst);
+                if !multi.finish() {
+                    return Err(EventError::invalid("The match isn't in progress").into());
+                }
+                broadcast(&multi, &match_complete()).await;
+                "Aborted the match".to_string()
+            }
+            "team" => {
+                let username = args.next("user")?;
+                let team = args.parse_with("team", |s| match s.to_lowercase().as_str() {
+                    "red" => Some(Team::Red),
+                    "blue" => Some(Team::Blue),
+                    _ => None,
+                })?;
+                let slot = player_slot(&multi, username).await?;
+                multi.set_team(slot, team);
+                format!("Moved {} to team {:?}", username, team)
+            }
+            "password" => {
+                let password = args.rest();
+                broadcast(&multi, &match_change_password(&password)).await;
+                *multi.password.write().await = if password.is_empty() {
+                    None
+                } else {
+                    Some(password)
+                };
+                "Changed the match password".to_string()
+            }
+            "addref" => {
+                let username = args.next("user")?;
+                let id = user_id(username, ctx).await?;
+                let mut referees = multi.referees.write().await;
+                if !referees.contains(&id) {
+                    referees.push(id);
+                }
+                format!("Made {} a referee", username)
+            }
+            "removeref" => {
+                let username = args.next("user")?;
+                let id = user_id(username, ctx).await?;
+                multi.referees.write().await.retain(|&r| r != id);
+                format!("{} is no longer a referee", username)
+            }
+            _ => {
+                return Err(ArgError::Invalid {
+                    name: "command",
+                    value: sub.to_string(),
+                }
+                .into())
+            }
+        };
+        update(&multi, ctx.glob).await;
+        Ok(Some(reply))
+    }
+}
@@ -1,6 +1,23 @@
-use crate::token::Token;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
+use crate::{privileges::Privileges, token::Token};
+use sqlx::postgres::PgPool;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{Mutex, RwLock};
+
+/// How many of the most recent messages every channel remembers
+pub const HISTORY_SIZE: usize = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub from: String,
+    pub from_id: i32,
+    pub content: String,
+}
 
 #[derive(Debug)]
 pub struct Channel {
@@ -8,9 +25,71 @@ pub struct Channel {
     pub desc: String,
     pub users: RwLock<Vec<Arc<dyn Token>>>,
     pub public: bool,
+    pub auto_join: bool,
+    /// Needed to join the channel and see what's being said in it
+    pub read_privileges: Privileges,
+    /// Needed to send messages to the channel
+    pub write_privileges: Privileges,
+    pub history: Mutex<VecDeque<HistoryEntry>>,
+    /// Set when someone joins or leaves, so the user count gets sent out again
+    changed: AtomicBool,
+}
+
+/// Row of the `channels` table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelDef {
+    pub name: String,
+    pub desc: String,
+    pub auto_join: bool,
+    pub read_privileges: Privileges,
+    pub write_privileges: Privileges,
+}
+
+impl ChannelDef {
+    pub async fn load_all(pool: &PgPool) -> Result<Vec<ChannelDef>, sqlx::Error> {
+        let rows: Vec<(String, String, bool, i32, i32)> = sqlx::query_as(
+            "SELECT name, description, auto_join, read_privileges, write_privileges FROM channels",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(name, desc, auto_join, read, write)| ChannelDef {
+                name,
+                desc,
+                auto_join,
+                read_privileges: Privileges(read as u32),
+                write_privileges: Privileges(write as u32),
+            })
+            .collect())
+    }
+
+    pub async fn insert(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO channels (name, description, auto_join, read_privileges, write_privileges)
+            VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&self.name)
+        .bind(&self.desc)
+        .bind(self.auto_join)
+        .bind(self.read_privileges.0 as i32)
+        .bind(self.write_privileges.0 as i32)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete(name: &str, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM channels WHERE name = $1")
+            .bind(name)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
 }
 
 impl Channel {
+    /// Creates a temporary channel anyone can use, like the ones for matches
     pub fn new(
         list: &mut HashMap<String, Arc<Channel>>,
         name: &str,
@@ -22,12 +101,42 @@ impl Channel {
             desc: desc.to_string(),
             users: RwLock::default(),
             public,
+            auto_join: false,
+            read_privileges: Privileges::default(),
+            write_privileges: Privileges::default(),
+            history: Mutex::default(),
+            changed: AtomicBool::default(),
         };
         let res = Arc::new(res);
         list.insert(name.to_string(), res.clone());
         res
     }
 
+    pub fn from_def(list: &mut HashMap<String, Arc<Channel>>, def: ChannelDef) -> Arc<Channel> {
+        let res = Channel {
+            name: def.name.clone(),
+            desc: def.desc,
+            users: RwLock::default(),
+            public: true,
+            auto_join: def.auto_join,
+            read_privileges: def.read_privileges,
+            write_privileges: def.write_privileges,
+            history: Mutex::default(),
+            changed: AtomicBool::default(),
+        };
+        let res = Arc::new(res);
+        list.insert(def.name, res.clone());
+        res
+    }
+
+    pub fn can_read(&self, token: &dyn Token) -> bool {
+        token.privileges().has(self.read_privileges)
+    }
+
+    pub fn can_write(&self, token: &dyn Token) -> bool {
+        token.privileges().has(self.write_privileges)
+    }
+
     pub fn name(&self) -> &str {
         if self.name.starts_with("#multi") {
             "#multiplayer"
@@ -53,6 +162,7 @@ impl Channel {
         }
 
         self.users.write().await.push(token);
+        self.changed.store(true, Ordering::SeqCst);
         true
     }
 
@@ -60,9 +170,26 @@ impl Channel {
         let mut users = self.users.write().await;
         if let Some(pos) = users.iter().position(|t| Arc::ptr_eq(t, token)) {
             users.remove(pos);
+            self.changed.store(true, Ordering::SeqCst);
             true
         } else {
             false
         }
     }
+
+    /// Whether the user count changed since the last time this was called
+    pub fn take_changed(&self) -> bool { self.changed.swap(false, Ordering::SeqCst) }
+
+    /// Remembers the message, forgetting the oldest one once there's too many
+    pub async fn push_history(&self, entry: HistoryEntry) {
+        let mut history = self.history.lock().await;
+        if history.len() >= HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(entry);
+    }
+
+    pub async fn history(&self) -> Vec<HistoryEntry> {
+        self.history.lock().await.iter().cloned().collect()
+    }
 }
@@ -1,28 +1,48 @@
-use crate::{event_data, packets::server::user_panel, token::player::Action, Glob, Token};
+use crate::{
+    events::{EventError, EventResult},
+    packets::{server::user_panel, Id, OsuDecode, OsuPacket},
+    token::player::{Action, GameMode},
+    Glob, Token,
+};
 use std::convert::TryFrom;
 
-#[event_data]
-#[derive(Debug)]
-struct ActionData {
+#[derive(OsuPacket, Debug)]
+#[packet(Id::ChangeAction)]
+struct ActionData<'a> {
     id: u8,
-    text: &str,
-    md5: &str,
+    text: &'a str,
+    md5: &'a str,
     mods: u32,
+    game_mode: u8,
+    beatmap_id: u32,
 }
 
-pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> Result<(), String> {
-    let data = ActionData::decode(data).map_err(|_| "Couldn't decode data".to_string())?;
-    let action = Action::try_from(data.id).map_err(|_| format!("Unknown action id {}", data.id))?;
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (data, _) =
+        ActionData::decode(data).map_err(|_| EventError::decode("Couldn't decode data"))?;
+    let action = Action::try_from(data.id)
+        .map_err(|_| EventError::invalid(format!("Unknown action id {}", data.id)))?;
+    let game_mode = GameMode::try_from(data.game_mode)
+        .map_err(|_| EventError::invalid(format!("Unknown game mode {}", data.game_mode)))?;
+    let mode_stats = match token.as_player() {
+        Some(player) => Some(player.mode_stats.read().await[game_mode as usize]),
+        None => None,
+    };
     // Update user's stats and drop the r/w lock
     {
         let mut s = token
             .stats_mut()
             .await
-            .ok_or_else(|| "Can't update stats as a dummy token".to_string())?;
+            .ok_or_else(|| EventError::permission("Can't update stats as a dummy token"))?;
         s.action = action;
         s.action_text = data.text.to_string();
         s.action_md5 = data.md5.to_string();
         s.action_mods = data.mods;
+        s.beatmap_id = data.beatmap_id;
+        s.game_mode = game_mode;
+        if let Some(mode_stats) = mode_stats {
+            s.set_mode_stats(&mode_stats);
+        }
     }
     let packet = user_panel(token);
     for t in glob.token_list.read().await.values() {
@@ -1,23 +1,36 @@
 use crate::{
-    packets::{server::channel_join_success, OsuEncode},
+    events::{EventError, EventResult},
+    packets::{
+        server::{channel_join_success, message_from},
+        OsuDecode,
+    },
     token::Token,
     Glob,
 };
 use std::sync::Arc;
 
-pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
-    let (name, _) = str::decode(data).map_err(|_| "Couldn't parse channel's name")?;
+pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let (name, _) =
+        <&str>::decode(data).map_err(|_| EventError::decode("Couldn't parse channel's name"))?;
     println!("\nCHANNEL JOIN {:?} {}\n", token.as_ref(), name);
     match glob.channel_list.read().await.get(name) {
+        Some(channel) if !channel.can_read(token.as_ref()) => {
+            Err(EventError::permission(format!("You can't join {}", name)))
+        }
         Some(channel) => {
             if channel.user_join(token.clone()).await {
                 token.join_channel(Arc::downgrade(channel)).await;
                 token.enqueue_vec(channel_join_success(channel)).await;
+                for entry in channel.history().await {
+                    let packet =
+                        message_from(&entry.from, entry.from_id, channel.name(), &entry.content);
+                    token.enqueue_vec(packet).await;
+                }
                 Ok(())
             } else {
-                Err(format!("Couldn't join channel {}", name))
+                Err(EventError::invalid(format!("Couldn't join channel {}", name)))
             }
         }
-        None => Err(format!("Couldn't find channel {}", name)),
+        None => Err(EventError::not_found(format!("Couldn't find channel {}", name))),
     }
 }
@@ -0,0 +1,94 @@
+use super::{EventError, EventResult};
+use crate::{
+    channel::ChannelDef,
+    packets::server::{channel_info, channel_kicked},
+    Channel, Glob,
+};
+use std::{sync::Arc, time::Duration};
+
+/// How often the user counts of channels get sent out at most
+pub const COUNT_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Adds the channel to the database and shows it to everyone allowed to read it
+pub async fn create(def: ChannelDef, glob: &Glob) -> EventResult {
+    if !def.name.starts_with('#') || def.name.len() < 2 {
+        return Err(EventError::invalid("Channel names have to start with #"));
+    }
+    if glob.channel_list.read().await.contains_key(&def.name) {
+        return Err(EventError::invalid(format!("{} already exists", def.name)));
+    }
+    def.insert(&glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't save the channel: {}", e)))?;
+    let channel = Channel::from_def(&mut *glob.channel_list.write().await, def);
+    channel.user_join(glob.bot.clone()).await;
+    let packet = channel_info(&channel).await;
+    for t in glob.token_list.read().await.values() {
+        if channel.can_read(t.as_ref()) {
+            t.enqueue(&packet).await;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the channel, kicking out everyone who was in it or could see it
+pub async fn remove(name: &str, glob: &Glob) -> EventResult {
+    match glob.channel_list.read().await.get(name) {
+        Some(channel) if channel.public => (),
+        Some(_) => return Err(EventError::invalid(format!("{} can't be removed", name))),
+        None => return Err(EventError::not_found(format!("No channel named {}", name))),
+    }
+    // Only forget about the channel once it's gone for good
+    ChannelDef::delete(name, &glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't delete the channel: {}", e)))?;
+    let channel = glob
+        .channel_list
+        .write()
+        .await
+        .remove(name)
+        .ok_or_else(|| EventError::not_found(format!("No channel named {}", name)))?;
+    let users: Vec<_> = channel.users.write().await.drain(..).collect();
+    for t in &users {
+        t.part_channel(&channel).await;
+    }
+    // Everyone who saw the channel in their list has to drop it, not only its members
+    let packet = channel_kicked(&channel);
+    for t in glob.token_list.read().await.values() {
+        if channel.can_read(t.as_ref()) || users.iter().any(|u| u.token() == t.token()) {
+            t.enqueue(&packet).await;
+        }
+    }
+    Ok(())
+}
+
+/// Sends the new user count of every public channel someone joined or left
+/// to everyone who can see it
+pub async fn update_counts(glob: &Glob) {
+    let changed: Vec<_> = glob
+        .channel_list
+        .read()
+        .await
+        .values()
+        .filter(|ch| ch.take_changed() && ch.public)
+        .cloned()
+        .collect();
+    for channel in changed {
+        let packet = channel_info(&channel).await;
+        for t in glob.token_list.read().await.values() {
+            if channel.can_read(t.as_ref()) {
+                t.enqueue(&packet).await;
+            }
+        }
+    }
+}
+
+/// Runs `update_counts` every `COUNT_UPDATE_INTERVAL`, so a lot of people
+/// logging in at once doesn't flood everyone with channel updates
+pub async fn update_counts_forever(glob: Arc<Glob>) {
+    let mut interval = tokio::time::interval(COUNT_UPDATE_INTERVAL);
+    loop {
+        interval.tick().await;
+        update_counts(&glob).await;
+    }
+}
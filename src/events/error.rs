@@ -0,0 +1,78 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Client sent a packet which doesn't follow the format at all,
+    /// well-formed packets with values we don't know about are `Invalid`
+    Decode,
+    /// User isn't allowed to do what they asked for
+    Permission,
+    /// Something the user referred to doesn't exist
+    NotFound,
+    /// Request makes no sense in the current state (full match, already joined, etc.)
+    Invalid,
+    /// Our own fault, the user only gets a generic message
+    Internal,
+}
+
+impl ErrorKind {
+    /// Whether the player should get a notification about the error
+    pub fn notifies(self) -> bool { self != ErrorKind::Decode }
+
+    /// Whether the error should be logged at the error level
+    pub fn is_error(self) -> bool {
+        match self {
+            ErrorKind::Decode | ErrorKind::Internal => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the player's session should be dropped,
+    /// a client sending broken packets can't be trusted with any more of them
+    pub fn drops_session(self) -> bool { self == ErrorKind::Decode }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventError {
+    pub kind: ErrorKind,
+    pub msg: String,
+}
+
+pub type EventResult = Result<(), EventError>;
+
+impl EventError {
+    pub fn new(kind: ErrorKind, msg: impl Into<String>) -> Self {
+        EventError {
+            kind,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn decode(msg: impl Into<String>) -> Self { EventError::new(ErrorKind::Decode, msg) }
+
+    pub fn permission(msg: impl Into<String>) -> Self {
+        EventError::new(ErrorKind::Permission, msg)
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self { EventError::new(ErrorKind::NotFound, msg) }
+
+    pub fn invalid(msg: impl Into<String>) -> Self { EventError::new(ErrorKind::Invalid, msg) }
+
+    pub fn internal(msg: impl Into<String>) -> Self { EventError::new(ErrorKind::Internal, msg) }
+
+    /// Message which is safe to show to the player
+    pub fn client_message(&self) -> &str {
+        match self.kind {
+            ErrorKind::Internal => "Something went wrong on our side, please try again later",
+            _ => &self.msg,
+        }
+    }
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} error: {}", self.kind, self.msg)
+    }
+}
+
+impl std::error::Error for EventError {}
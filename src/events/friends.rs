@@ -0,0 +1,87 @@
+use crate::{
+    events::{EventError, EventResult},
+    packets::OsuDecode,
+    Glob, Token,
+};
+use std::sync::atomic::Ordering;
+
+pub async fn add(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let (id, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode user id"))?;
+    if id == token.id() {
+        return Err(EventError::invalid("You can't befriend yourself"));
+    }
+    if player.is_friend(id).await {
+        return Ok(());
+    }
+    let user: Option<(i32,)> = sqlx::query_as("SELECT id FROM users WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't look up user: {}", e)))?;
+    if user.is_none() {
+        return Err(EventError::not_found(format!("User {} doesn't exist", id)));
+    }
+    sqlx::query("INSERT INTO friends (user_id, friend_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(token.id())
+        .bind(id)
+        .execute(&glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't add friend: {}", e)))?;
+    player.friends.write().await.push(id);
+    Ok(())
+}
+
+pub async fn remove(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let (id, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode user id"))?;
+    sqlx::query("DELETE FROM friends WHERE user_id = $1 AND friend_id = $2")
+        .bind(token.id())
+        .bind(id)
+        .execute(&glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't remove friend: {}", e)))?;
+    player.friends.write().await.retain(|&f| f != id);
+    Ok(())
+}
+
+pub async fn toggle_block_non_friends(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let (value, _) = u32::decode(data).map_err(|_| EventError::decode("Couldn't decode data"))?;
+    player.block_non_friends.store(value != 0, Ordering::SeqCst);
+    save_block_non_friends(token.id(), value != 0, glob)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't save the setting: {}", e)))
+}
+
+/// Stores the setting so that it also applies to messages sent while the user is offline
+pub async fn save_block_non_friends(id: i32, block: bool, glob: &Glob) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO user_settings (user_id, block_non_friends) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET block_non_friends = $2",
+    )
+    .bind(id)
+    .bind(block)
+    .execute(&glob.db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Whether the user only accepted messages from friends during their last session
+pub async fn load_block_non_friends(id: i32, glob: &Glob) -> Result<bool, sqlx::Error> {
+    let row: Option<(bool,)> =
+        sqlx::query_as("SELECT block_non_friends FROM user_settings WHERE user_id = $1")
+            .bind(id)
+            .fetch_optional(&glob.db_pool)
+            .await?;
+    Ok(row.map_or(false, |(block,)| block))
+}
+
+/// Loads ids of the user's friends from the database
+pub async fn load(id: i32, glob: &Glob) -> Result<Vec<i32>, sqlx::Error> {
+    let rows: Vec<(i32,)> = sqlx::query_as("SELECT friend_id FROM friends WHERE user_id = $1")
+        .bind(id)
+        .fetch_all(&glob.db_pool)
+        .await?;
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
@@ -0,0 +1,39 @@
+use super::{EventError, EventResult};
+use crate::{
+    audit,
+    packets::server::{login_failed, notification},
+    privileges::Privileges,
+    Glob, Token,
+};
+use std::sync::atomic::Ordering;
+
+/// Disconnects the user on their next poll, telling them who kicked them and why
+pub async fn handle(username: &str, reason: &str, issuer: &dyn Token, glob: &Glob) -> EventResult {
+    let target = glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.username() == username)
+        .cloned()
+        .ok_or_else(|| EventError::not_found(format!("{} is not online", username)))?;
+    let player = target
+        .as_player()
+        .ok_or_else(|| EventError::invalid("Bots can't be kicked"))?;
+    if target.privileges().has(Privileges::ADMIN) && !issuer.privileges().has(Privileges::ADMIN) {
+        return Err(EventError::permission("You can't kick an admin"));
+    }
+
+    let mut msg = format!("You have been kicked by {}!", issuer.username());
+    if !reason.is_empty() {
+        msg += &format!("\nReason: \"{}\"", reason);
+    }
+    // The client only goes back to the login screen once it gets both of these,
+    // so the token has to stay around until its next poll
+    target.enqueue_vec(notification(&msg)).await;
+    target.enqueue_vec(login_failed()).await;
+    player.kicked.store(true, Ordering::SeqCst);
+    audit::log(issuer.id(), target.id(), "kick", reason, glob)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't write to the audit log: {}", e)))
+}
@@ -1,7 +1,11 @@
-use crate::{packets::server::create_match, Glob, Token};
+use crate::{
+    events::{EventError, EventResult},
+    packets::server::create_match,
+    Glob, Token,
+};
 use std::sync::Arc;
 
-pub async fn handle(token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
+pub async fn handle(token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
     if glob
         .lobby
         .read()
@@ -9,7 +13,7 @@ pub async fn handle(token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
         .iter()
         .any(|t| Arc::ptr_eq(t, token))
     {
-        return Err("But you already are in the lobby??".to_string());
+        return Err(EventError::invalid("But you already are in the lobby??"));
     }
 
     glob.lobby.write().await.push(token.clone());
@@ -1,12 +1,15 @@
-use crate::{Glob, Token};
+use crate::{
+    events::{EventError, EventResult},
+    Glob, Token,
+};
 use std::sync::Arc;
 
-pub async fn handle(token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
+pub async fn handle(token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
     let mut lobby = glob.lobby.write().await;
     let pos = lobby
         .iter()
         .position(|t| Arc::ptr_eq(t, token))
-        .ok_or_else(|| "You aren't in the lobby".to_string())?;
+        .ok_or_else(|| EventError::invalid("You aren't in the lobby"))?;
     lobby.remove(pos);
     Ok(())
 }
@@ -1,12 +1,45 @@
+use super::{matches, spectate, EventError, EventResult};
 use crate::{packets::server::logout, Glob};
+use std::sync::Arc;
 
-pub async fn handle(token: &str, glob: &Glob) -> Result<(), &'static str> {
+pub async fn handle(token: &str, glob: &Glob) -> EventResult {
     let user = glob
         .token_list
         .write()
         .await
         .remove(token)
-        .ok_or("No such user logged in")?;
+        .ok_or_else(|| EventError::not_found("No such user logged in"))?;
+    if let Some(player) = user.as_player() {
+        let host = player.spectating.lock().await.take().and_then(|h| h.upgrade());
+        if let Some(host) = host {
+            spectate::leave(&host, &user, glob).await;
+        }
+        let spectators = player.spectators.read().await.clone();
+        for s in spectators.iter() {
+            if let Some(p) = s.as_player() {
+                *p.spectating.lock().await = None;
+            }
+            spectate::leave(&user, s, glob).await;
+        }
+        let multi = player.multi.lock().await.take().and_then(|m| m.upgrade());
+        if let Some(multi) = multi {
+            matches::leave(&multi, &user, glob).await;
+        }
+    }
+    glob.lobby.write().await.retain(|t| !Arc::ptr_eq(t, &user));
+    // Matches the user was refereeing shouldn't outlive them if nobody is playing
+    let list: Vec<_> = glob.match_list.read().await.values().cloned().collect();
+    for multi in list {
+        let mut referees = multi.referees.write().await;
+        if !referees.contains(&user.id()) {
+            continue;
+        }
+        referees.retain(|&id| id != user.id());
+        if referees.is_empty() && multi.is_empty() {
+            drop(referees);
+            matches::dispose(&multi, glob).await;
+        }
+    }
     for c in user.channels().await.iter() {
         let c = match c.upgrade() {
             Some(c) => c,
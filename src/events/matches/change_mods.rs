@@ -0,0 +1,40 @@
+use super::{player_slot, update};
+use crate::{
+    events::{EventError, EventResult},
+    packets::OsuDecode,
+    Glob, Token,
+};
+use std::sync::atomic::Ordering;
+use tracing::instrument;
+
+/// DT, HT and NC change the song's speed so they have to be the same for everyone
+pub const SPEED_MODS: u32 = 64 | 256 | 512;
+
+/// With freemod on players pick their own mods and the host picks the speed,
+/// otherwise only the host can change the mods of the whole match
+#[instrument(skip(data, token, glob), target = "match_change_mods")]
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (mods, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode mods"))?;
+    let mods = mods as u32;
+    let (multi, slot) = player_slot(token).await?;
+    let is_host = *multi.host_id.read().await == token.id();
+    let match_mods = if multi.freemod.load(Ordering::SeqCst) {
+        multi.slots[slot].mods.store(mods & !SPEED_MODS, Ordering::SeqCst);
+        if is_host {
+            Some(mods & SPEED_MODS)
+        } else {
+            None
+        }
+    } else if is_host {
+        Some(mods)
+    } else {
+        return Err(EventError::permission("Only the host can change the mods"));
+    };
+    if let Some(mods) = match_mods {
+        if multi.mods.swap(mods, Ordering::SeqCst) != mods {
+            multi.unready_all();
+        }
+    }
+    update(&multi, glob).await;
+    Ok(())
+}
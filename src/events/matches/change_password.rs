@@ -0,0 +1,26 @@
+use super::{broadcast, decode_settings, player_match, update};
+use crate::{
+    events::{EventError, EventResult},
+    packets::server::match_change_password,
+    Glob, Token,
+};
+use tracing::instrument;
+
+/// Only the players in the match get to see the new password,
+/// the lobby is just told that there is one
+#[instrument(skip(data, token, glob), target = "match_change_password")]
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let multi = player_match(token).await?;
+    if *multi.host_id.read().await != token.id() {
+        return Err(EventError::permission("Only the host can change the password"));
+    }
+    let data = decode_settings(data)?;
+    *multi.password.write().await = if data.password.is_empty() {
+        None
+    } else {
+        Some(data.password.to_string())
+    };
+    broadcast(&multi, &match_change_password(data.password)).await;
+    update(&multi, glob).await;
+    Ok(())
+}
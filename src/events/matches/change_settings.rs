@@ -1,28 +1,21 @@
-use super::MatchSettings;
-use crate::{bot, packets::server::update_match, r#match::SlotStatus, Glob, Token};
+use super::{broadcast_lobby, decode_settings, player_match};
+use crate::{
+    bot,
+    events::{EventError, EventResult},
+    packets::server::{lobby_update_match, update_match},
+    r#match::SlotStatus,
+    Glob, Token,
+};
 use std::sync::atomic::Ordering;
 use tracing::{error, instrument};
 
 #[instrument(skip(data, token, glob), target = "change_match_settings")]
-pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> Result<(), String> {
-    let player = token.as_player().ok_or("You're a bot")?;
-    let multi = {
-        let mut mutex = player.multi.lock().await;
-        mutex
-            .as_ref()
-            .ok_or_else(|| "You aren't in a multiplayer lobby")?
-            .clone()
-            .upgrade()
-            .ok_or_else(|| {
-                *mutex = None;
-                "The lobby doesn't exist anymore".to_string()
-            })?
-    };
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let multi = player_match(token).await?;
     if *multi.host_id.read().await != token.id() {
-        return Err("You ain't even the host here".to_string());
+        return Err(EventError::permission("You ain't even the host here"));
     }
-    let data =
-        MatchSettings::decode(data).map_err(|_| "Couldn't decode match settings".to_string())?;
+    let data = decode_settings(data)?;
 
     *multi.name.write().await = data.name.to_string();
     multi.in_progress.store(data.in_progress, Ordering::SeqCst);
@@ -37,13 +30,7 @@ pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> Result<(), S
     multi.game_mode.store(data.game_mode, Ordering::SeqCst);
     let mods_old = multi.mods.swap(data.mods, Ordering::SeqCst);
     if mods_old != data.mods || md5_old != data.beatmap_md5 {
-        for slot in multi.slots.iter() {
-            slot.status.compare_and_swap(
-                SlotStatus::Ready as u8,
-                SlotStatus::NotReady as u8,
-                Ordering::SeqCst,
-            );
-        }
+        multi.unready_all();
     }
     multi.freemod.store(data.freemod, Ordering::SeqCst);
     let packet = update_match(&multi).await;
@@ -60,12 +47,13 @@ pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> Result<(), S
                 slot.status.store(SlotStatus::Free as u8, Ordering::SeqCst);
                 bot::send_message(
                     "Looks like a slot in this match got corrupted, the issue should be fixed next time settings of this match are updated",
-                    &format!("#multi_{}", multi.id),
+                    &multi.channel_name(),
                     glob).await.map_err(|e| {
                         error!("Couln't send a message to multi channel! (error: {:?})", e);
                     }).ok();
             }
         }
     }
+    broadcast_lobby(glob, &lobby_update_match(&multi).await).await;
     Ok(())
 }
@@ -0,0 +1,25 @@
+use super::{player_slot, update};
+use crate::{
+    events::{EventError, EventResult},
+    packets::OsuDecode,
+    Glob, Token,
+};
+use std::sync::atomic::Ordering;
+use tracing::instrument;
+
+#[instrument(skip(data, token, glob), target = "match_change_slot")]
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (to, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode slot"))?;
+    let (multi, from) = player_slot(token).await?;
+    if to < 0 || to as usize >= multi.slots.len() {
+        return Err(EventError::invalid(format!("There's no slot {}", to)));
+    }
+    if multi.in_progress.load(Ordering::SeqCst) {
+        return Err(EventError::invalid("You can't change slots during a match"));
+    }
+    if !multi.move_player(from, to as usize).await {
+        return Err(EventError::invalid("That slot isn't free"));
+    }
+    update(&multi, glob).await;
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use super::{player_slot, update};
+use crate::{
+    events::{EventError, EventResult},
+    Glob, Token,
+};
+use tracing::instrument;
+
+#[instrument(skip(token, glob), target = "match_change_team")]
+pub async fn handle(token: &dyn Token, glob: &Glob) -> EventResult {
+    let (multi, slot) = player_slot(token).await?;
+    if !multi.is_team_mode() {
+        return Err(EventError::invalid("The match isn't played in teams"));
+    }
+    multi.change_team(slot);
+    update(&multi, glob).await;
+    Ok(())
+}
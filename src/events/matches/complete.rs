@@ -0,0 +1,11 @@
+use super::{broadcast, player_slot, update};
+use crate::{events::EventResult, packets::server::match_complete, Glob, Token};
+
+pub async fn handle(token: &dyn Token, glob: &Glob) -> EventResult {
+    let (multi, slot) = player_slot(token).await?;
+    if multi.complete(slot) {
+        broadcast(&multi, &match_complete()).await;
+    }
+    update(&multi, glob).await;
+    Ok(())
+}
@@ -1,19 +1,29 @@
-use super::MatchSettings;
 use crate::{
+    events::{EventError, EventResult},
     packets::server::{
-        channel_join_success, create_match, match_join_success, match_transfer_host,
+        channel_join_success, create_match, match_join_fail, match_join_success,
+        match_transfer_host,
     },
     token::Token,
     Channel, Glob, Match,
 };
 use std::sync::Arc;
+use tracing::trace;
 
-pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
-    let player = token.as_player().ok_or("You're a bot")?;
-    let data =
-        MatchSettings::decode(data).map_err(|_| "Couldn't parse match settings".to_string())?;
+pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let data = match super::decode_settings(data) {
+        Ok(data) => data,
+        Err(e) => {
+            token.enqueue_vec(match_join_fail()).await;
+            return Err(e);
+        }
+    };
 
-    println!("NEW MATCH! {:?}", data);
+    let old = player.multi.lock().await.take().and_then(|m| m.upgrade());
+    if let Some(old) = old {
+        super::part::leave(&old, token, glob).await;
+    }
     let m = {
         let mut list = glob.match_list.write().await;
         Match::new(
@@ -27,6 +37,13 @@ pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<
         )
         .await
     };
+    let m = match m {
+        Some(m) => m,
+        None => {
+            token.enqueue_vec(match_join_fail()).await;
+            return Err(EventError::internal("There are too many matches"));
+        }
+    };
     *(player.multi.lock().await) = Some(Arc::downgrade(&m));
     token.enqueue_vec(match_join_success(&m).await).await;
     token.enqueue_vec(match_transfer_host()).await;
@@ -39,12 +56,12 @@ pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<
     }
     let ch = {
         let mut list = glob.channel_list.write().await;
-        Channel::new(&mut list, &format!("#multi_{}", m.id), "", false)
+        Channel::new(&mut list, &m.channel_name(), "", false)
     };
     if ch.user_join(token.clone()).await {
         token.join_channel(Arc::downgrade(&ch)).await;
         token.enqueue_vec(channel_join_success(&ch)).await;
     }
-    println!("MATCH CREATED: {:?}", m);
+    trace!(id = m.id, "match created");
     Ok(())
 }
@@ -0,0 +1,8 @@
+use super::{broadcast_playing, player_slot};
+use crate::{events::EventResult, packets::server::match_player_failed, Token};
+
+pub async fn handle(token: &dyn Token) -> EventResult {
+    let (multi, slot) = player_slot(token).await?;
+    broadcast_playing(&multi, &match_player_failed(slot as i32)).await;
+    Ok(())
+}
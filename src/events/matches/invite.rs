@@ -0,0 +1,45 @@
+use super::player_match;
+use crate::{
+    events::{EventError, EventResult},
+    packets::{
+        server::{match_invite, send_message},
+        OsuDecode,
+    },
+    Glob, Match, Token,
+};
+use tracing::instrument;
+
+#[instrument(skip(data, token, glob), target = "match_invite")]
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (id, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode user id"))?;
+    let multi = player_match(token).await?;
+    if *multi.host_id.read().await != token.id() {
+        return Err(EventError::permission("Only the host can invite people"));
+    }
+    let target = glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.id() == id)
+        .cloned()
+        .ok_or_else(|| EventError::not_found("That user isn't online"))?;
+    send_invite(&multi, token, target.as_ref(), glob).await;
+    Ok(())
+}
+
+/// Sends the target a link to the match, as a message from the bot and as an invite
+pub(crate) async fn send_invite(multi: &Match, from: &dyn Token, target: &dyn Token, glob: &Glob) {
+    let password = multi.password.read().await.clone().unwrap_or_default();
+    let msg = format!(
+        "{} invited you to [osump://{}/{} {}]",
+        from.username(),
+        multi.id,
+        password,
+        *multi.name.read().await
+    );
+    target
+        .enqueue_vec(send_message(glob.bot.as_ref(), target.username(), &msg))
+        .await;
+    target.enqueue_vec(match_invite(from, target.username(), &msg)).await;
+}
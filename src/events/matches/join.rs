@@ -0,0 +1,77 @@
+use super::update;
+use crate::{
+    events::{EventError, EventResult},
+    packets::{
+        server::{channel_join_success, match_join_fail, match_join_success},
+        Id, OsuDecode, OsuPacket,
+    },
+    token::Token,
+    Glob,
+};
+use std::{convert::TryFrom, sync::Arc};
+use tracing::instrument;
+
+#[derive(OsuPacket, Debug)]
+#[packet(Id::MatchJoin)]
+struct JoinData<'a> {
+    id: i32,
+    password: &'a str,
+}
+
+#[instrument(skip(data, token, glob), target = "match_join")]
+pub async fn handle(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let (data, _) =
+        JoinData::decode(data).map_err(|_| EventError::decode("Couldn't decode match data"))?;
+    let multi = match u16::try_from(data.id) {
+        Ok(id) => glob.match_list.read().await.get(&id).cloned(),
+        Err(_) => None,
+    };
+    let multi = match multi {
+        Some(m) => m,
+        None => {
+            token.enqueue_vec(match_join_fail()).await;
+            return Err(EventError::not_found(format!("Match {} doesn't exist", data.id)));
+        }
+    };
+    if let Some(pass) = &*multi.password.read().await {
+        if pass != data.password {
+            token.enqueue_vec(match_join_fail()).await;
+            return Err(EventError::permission("Wrong password"));
+        }
+    }
+
+    let old = player.multi.lock().await.take().and_then(|m| m.upgrade());
+    if let Some(old) = old {
+        if Arc::ptr_eq(&old, &multi) {
+            // Leaving first could dispose of the match we're about to join
+            *player.multi.lock().await = Some(Arc::downgrade(&old));
+            token.enqueue_vec(match_join_success(&multi).await).await;
+            return Ok(());
+        }
+        super::part::leave(&old, token, glob).await;
+    }
+
+    if multi.join(token).await.is_none() {
+        token.enqueue_vec(match_join_fail()).await;
+        return Err(EventError::invalid("The match is full"));
+    }
+    *player.multi.lock().await = Some(Arc::downgrade(&multi));
+    token.enqueue_vec(match_join_success(&multi).await).await;
+
+    let ch = glob
+        .channel_list
+        .read()
+        .await
+        .get(&multi.channel_name())
+        .cloned();
+    if let Some(ch) = ch {
+        if ch.user_join(token.clone()).await {
+            token.join_channel(Arc::downgrade(&ch)).await;
+            token.enqueue_vec(channel_join_success(&ch)).await;
+        }
+    }
+
+    update(&multi, glob).await;
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use super::{broadcast_playing, player_slot};
+use crate::{events::EventResult, packets::server::match_all_players_loaded, Token};
+
+pub async fn handle(token: &dyn Token) -> EventResult {
+    let (multi, slot) = player_slot(token).await?;
+    if multi.load_complete(slot) {
+        broadcast_playing(&multi, &match_all_players_loaded()).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use super::{leave, player_match, update};
+use crate::{
+    events::{EventError, EventResult},
+    packets::{server::dispose_match, OsuDecode},
+    Glob, Token,
+};
+use tracing::instrument;
+
+/// Locks or unlocks the slot, kicking out whoever is sitting in it
+#[instrument(skip(data, token, glob), target = "match_lock")]
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (slot, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode slot"))?;
+    let multi = player_match(token).await?;
+    if *multi.host_id.read().await != token.id() {
+        return Err(EventError::permission("Only the host can lock slots"));
+    }
+    let target = multi
+        .slots
+        .get(slot as usize)
+        .ok_or_else(|| EventError::invalid(format!("There's no slot {}", slot)))?
+        .token
+        .read()
+        .await
+        .clone();
+    let slot = slot as usize;
+    if let Some(target) = target {
+        if target.id() == token.id() {
+            return Err(EventError::invalid("You can't lock your own slot"));
+        }
+        if let Some(player) = target.as_player() {
+            *player.multi.lock().await = None;
+        }
+        // Takes the client back to the lobby
+        target.enqueue_vec(dispose_match(multi.id)).await;
+        leave(&multi, &target, glob).await;
+    }
+    if !multi.toggle_lock(slot) {
+        return Err(EventError::invalid("The slot can't be locked right now"));
+    }
+    update(&multi, glob).await;
+    Ok(())
+}
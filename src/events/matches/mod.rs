@@ -1,71 +1,119 @@
 use crate::{
-    packets::OsuEncode,
-    r#match::{ScoringType, SlotStatus, Team, TeamType},
+    events::EventError,
+    packets::{
+        server::{lobby_update_match, update_match},
+        DecodeError, OsuDecode,
+    },
+    r#match::{MatchInfo, SlotStatus},
+    Glob, Match, Token,
 };
+use std::sync::{atomic::Ordering, Arc};
 
 mod create;
 pub use create::handle as create;
 mod change_settings;
-use crate::event_data;
 pub use change_settings::handle as change_settings;
+mod join;
+pub use join::handle as join;
+mod part;
+pub(crate) use part::{dispose, leave};
+pub use part::handle as part;
+mod ready;
+pub use ready::{not_ready, ready};
+mod start;
+pub(crate) use start::begin;
+pub use start::handle as start;
+mod load_complete;
+pub use load_complete::handle as load_complete;
+mod skip;
+pub use skip::handle as skip;
+mod score_update;
+pub use score_update::handle as score_update;
+mod complete;
+pub use complete::handle as complete;
+mod failed;
+pub use failed::handle as failed;
+mod transfer_host;
+pub use transfer_host::handle as transfer_host;
+mod change_slot;
+pub use change_slot::handle as change_slot;
+mod lock;
+pub use lock::handle as lock;
+mod change_team;
+pub use change_team::handle as change_team;
+mod change_mods;
+pub use change_mods::{handle as change_mods, SPEED_MODS};
+mod invite;
+pub(crate) use invite::send_invite;
+pub use invite::handle as invite;
+mod change_password;
+pub use change_password::handle as change_password;
 
-fn get_slot_ids(data: &[u8], slot_statuses: &&[SlotStatus; 16]) -> Result<([i32; 16], usize), ()> {
-    let slot_statuses = *slot_statuses;
-    let mut off = 0;
-    let mut slot_ids = [-1i32; 16];
-    for (i, s) in slot_statuses.iter().enumerate() {
-        if !s.is_occupied() {
+/// Enqueues the packet for every player sitting in the match
+pub(crate) async fn broadcast(m: &Match, packet: &[u8]) {
+    for slot in m.slots.iter() {
+        if let Some(t) = slot.token.read().await.as_ref() {
+            t.enqueue(packet).await;
+        }
+    }
+}
+
+/// Enqueues the packet for every player currently playing in the match
+pub(crate) async fn broadcast_playing(m: &Match, packet: &[u8]) {
+    for slot in m.slots.iter() {
+        if slot.status.load(Ordering::SeqCst) != SlotStatus::Playing as u8 {
             continue;
         }
-        let (&id, o) = i32::decode(&data[off..])?;
-        off += o;
-        slot_ids[i] = id;
+        if let Some(t) = slot.token.read().await.as_ref() {
+            t.enqueue(packet).await;
+        }
     }
-    Ok((slot_ids, off))
 }
 
-fn get_slot_mods<'a>(data: &'a [u8], freemod: &bool) -> Result<(Option<&'a [i32; 16]>, usize), ()> {
-    if *freemod {
-        let (res, off) = <[i32; 16]>::decode(data)?;
-        Ok((Some(res), off))
-    } else {
-        Ok((None, 0))
+/// Enqueues the packet for everyone browsing the lobby
+pub(crate) async fn broadcast_lobby(glob: &Glob, packet: &[u8]) {
+    for t in glob.lobby.read().await.iter() {
+        t.enqueue(packet).await;
     }
 }
 
-fn parse_name<'a>(data: &'a [u8]) -> Result<(&'a str, usize), ()> {
-    let (name, off) = str::decode(data)?;
-    let name = name.trim();
-    if name.is_empty() {
-        Err(())
-    } else {
-        Ok((name, off))
+/// Sends the current state of the match to its players and the lobby
+pub(crate) async fn update(m: &Match, glob: &Glob) {
+    broadcast(m, &update_match(m).await).await;
+    broadcast_lobby(glob, &lobby_update_match(m).await).await;
+}
+
+pub(crate) async fn player_match(token: &dyn Token) -> Result<Arc<Match>, EventError> {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let mut mutex = player.multi.lock().await;
+    mutex
+        .as_ref()
+        .ok_or_else(|| EventError::invalid("You aren't in a multiplayer match"))?
+        .clone()
+        .upgrade()
+        .ok_or_else(|| {
+            *mutex = None;
+            EventError::not_found("The match doesn't exist anymore")
+        })
+}
+
+/// Settings with values we don't know about, like an empty name, are only rejected,
+/// settings which can't be read at all drop the session
+fn decode_settings(data: &[u8]) -> Result<MatchInfo<'_>, EventError> {
+    match MatchInfo::decode(data) {
+        Ok((info, _)) => Ok(info),
+        Err(DecodeError::InvalidValue) => Err(EventError::invalid(
+            "Invalid match settings, make sure the name isn't empty",
+        )),
+        Err(e) => Err(EventError::decode(format!("Couldn't decode match settings: {}", e))),
     }
 }
 
-#[event_data]
-#[derive(Debug)]
-struct MatchSettings {
-    id: u16,
-    in_progress: bool,
-    match_type: u8,
-    mods: u32,
-    #[decoder(parse_name)]
-    name: &str,
-    password: &str,
-    beatmap_name: &str,
-    beatmap_id: u32,
-    beatmap_md5: &str,
-    slot_statuses: &[SlotStatus; 16],
-    slot_teams: &[Team; 16],
-    #[decoder(get_slot_ids: slot_statuses)]
-    slot_ids: [i32; 16],
-    host_id: i32,
-    game_mode: u8,
-    scoring_type: ScoringType,
-    team_type: TeamType,
-    freemod: bool,
-    #[decoder(get_slot_mods: freemod)]
-    slot_mods: Option<&[i32; 16]>,
-    seed: i32,
+async fn player_slot(token: &dyn Token) -> Result<(Arc<Match>, usize), EventError> {
+    let multi = player_match(token).await?;
+    let slot = multi
+        .slot_of(token.id())
+        .await
+        .ok_or_else(|| EventError::internal("Player has no slot in their match"))?;
+    Ok((multi, slot))
 }
@@ -0,0 +1,84 @@
+use super::{broadcast, broadcast_lobby, update};
+use crate::{
+    events::{EventError, EventResult},
+    packets::server::{channel_kicked, dispose_match, match_complete, match_transfer_host},
+    token::Token,
+    Glob, Match,
+};
+use std::sync::Arc;
+use tracing::{instrument, trace};
+
+#[instrument(skip(token, glob), target = "match_part")]
+pub async fn handle(token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let multi = player
+        .multi
+        .lock()
+        .await
+        .take()
+        .and_then(|m| m.upgrade())
+        .ok_or_else(|| EventError::invalid("You aren't in a multiplayer match"))?;
+    leave(&multi, token, glob).await;
+    Ok(())
+}
+
+/// Frees the token's slot, hands the host over if needed
+/// and disposes of the match once nobody is left in it
+pub(crate) async fn leave(multi: &Arc<Match>, token: &Arc<dyn Token>, glob: &Glob) {
+    multi.part(token).await;
+    let ch = glob
+        .channel_list
+        .read()
+        .await
+        .get(&multi.channel_name())
+        .cloned();
+    if let Some(ch) = ch {
+        if ch.user_part(token).await {
+            token.enqueue_vec(channel_kicked(&ch)).await;
+        }
+    }
+
+    // Matches made by referees stay open until they close them
+    if multi.is_empty() && multi.referees.read().await.is_empty() {
+        dispose(multi, glob).await;
+        return;
+    }
+
+    if *multi.host_id.read().await == token.id() {
+        if let Some(host) = multi.first_player().await {
+            *multi.host_id.write().await = host.id();
+            host.enqueue_vec(match_transfer_host()).await;
+        }
+    }
+    // The player who left might have been the last one still playing
+    if multi.try_finish() {
+        broadcast(multi, &match_complete()).await;
+    }
+    update(multi, glob).await;
+}
+
+/// Removes the match and its channel from the global lists,
+/// does nothing if the match was already disposed of
+pub(crate) async fn dispose(multi: &Match, glob: &Glob) {
+    {
+        let mut list = glob.match_list.write().await;
+        // The id might already belong to a new match
+        if !list.get(&multi.id).map_or(false, |m| std::ptr::eq(&**m, multi)) {
+            return;
+        }
+        list.remove(&multi.id);
+    }
+    trace!(id = multi.id, "disposing of match");
+    broadcast_lobby(glob, &dispose_match(multi.id)).await;
+    let ch = glob
+        .channel_list
+        .write()
+        .await
+        .remove(&multi.channel_name());
+    if let Some(ch) = ch {
+        // Nobody should be left holding the channel once its id gets reused
+        for t in ch.users.write().await.drain(..) {
+            t.enqueue_vec(channel_kicked(&ch)).await;
+        }
+    }
+}
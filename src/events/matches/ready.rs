@@ -0,0 +1,22 @@
+use super::{player_slot, update};
+use crate::{
+    events::{EventError, EventResult},
+    Glob, Token,
+};
+
+pub async fn ready(token: &dyn Token, glob: &Glob) -> EventResult {
+    set_ready(token, true, glob).await
+}
+
+pub async fn not_ready(token: &dyn Token, glob: &Glob) -> EventResult {
+    set_ready(token, false, glob).await
+}
+
+async fn set_ready(token: &dyn Token, ready: bool, glob: &Glob) -> EventResult {
+    let (multi, slot) = player_slot(token).await?;
+    if !multi.set_ready(slot, ready) {
+        return Err(EventError::invalid("You can't change your status right now"));
+    }
+    update(&multi, glob).await;
+    Ok(())
+}
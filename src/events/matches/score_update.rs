@@ -0,0 +1,37 @@
+use super::player_slot;
+use crate::{
+    events::{EventError, EventResult},
+    packets::{server::match_score_update, DecodeError, OsuEncode, Reader},
+    r#match::SlotStatus,
+    Token,
+};
+use std::sync::atomic::Ordering;
+
+/// Splits the timestamp off the frame and skips the slot id the client sent,
+/// returning the rest of the frame as is
+fn decode_header(data: &[u8]) -> Result<(i32, &[u8]), DecodeError> {
+    let mut reader = Reader::new(data);
+    let time = reader.read::<i32>()?;
+    reader.read::<u8>()?;
+    Ok((time, reader.remaining()))
+}
+
+pub async fn handle(data: &[u8], token: &dyn Token) -> EventResult {
+    let (time, rest) =
+        decode_header(data).map_err(|_| EventError::decode("Couldn't decode score frame"))?;
+    let (multi, slot) = player_slot(token).await?;
+    let mut frame = Vec::with_capacity(data.len());
+    time.encode(&mut frame);
+    (slot as u8).encode(&mut frame);
+    frame.extend_from_slice(rest);
+    let packet = match_score_update(&frame);
+    for (i, s) in multi.slots.iter().enumerate() {
+        if i == slot || s.status.load(Ordering::SeqCst) != SlotStatus::Playing as u8 {
+            continue;
+        }
+        if let Some(t) = s.token.read().await.as_ref() {
+            t.enqueue(&packet).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use super::{broadcast_playing, player_slot};
+use crate::{
+    events::EventResult,
+    packets::server::{match_player_skipped, match_skip},
+    Token,
+};
+
+pub async fn handle(token: &dyn Token) -> EventResult {
+    let (multi, slot) = player_slot(token).await?;
+    let everyone = multi.skip(slot);
+    broadcast_playing(&multi, &match_player_skipped(slot as i32)).await;
+    if everyone {
+        broadcast_playing(&multi, &match_skip()).await;
+    }
+    Ok(())
+}
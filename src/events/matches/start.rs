@@ -0,0 +1,31 @@
+use super::{broadcast, broadcast_playing, player_match};
+use crate::{
+    events::{EventError, EventResult},
+    packets::server::{lobby_update_match, match_start, update_match},
+    Glob, Lobby, Match, Token,
+};
+use tracing::instrument;
+
+#[instrument(skip(token, glob), target = "match_start")]
+pub async fn handle(token: &dyn Token, glob: &Glob) -> EventResult {
+    let multi = player_match(token).await?;
+    if *multi.host_id.read().await != token.id() {
+        return Err(EventError::permission("Only the host can start the match"));
+    }
+    begin(&multi, &glob.lobby).await
+}
+
+/// Starts the match for everyone who's ready and tells them and the lobby about it,
+/// only takes the lobby so that countdowns can start matches without the whole glob
+pub(crate) async fn begin(multi: &Match, lobby: &Lobby) -> EventResult {
+    if !multi.start() {
+        return Err(EventError::invalid("The match is already in progress"));
+    }
+    broadcast_playing(multi, &match_start(multi).await).await;
+    broadcast(multi, &update_match(multi).await).await;
+    let packet = lobby_update_match(multi).await;
+    for t in lobby.read().await.iter() {
+        t.enqueue(&packet).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use super::{player_match, update};
+use crate::{
+    events::{EventError, EventResult},
+    packets::{server::match_transfer_host, OsuDecode},
+    Glob, Token,
+};
+use tracing::instrument;
+
+#[instrument(skip(data, token, glob), target = "match_transfer_host")]
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (slot, _) = i32::decode(data).map_err(|_| EventError::decode("Couldn't decode slot"))?;
+    let multi = player_match(token).await?;
+    if *multi.host_id.read().await != token.id() {
+        return Err(EventError::permission("Only the host can give it away"));
+    }
+    let host = multi
+        .slots
+        .get(slot as usize)
+        .ok_or_else(|| EventError::invalid(format!("There's no slot {}", slot)))?
+        .token
+        .read()
+        .await
+        .clone()
+        .ok_or_else(|| EventError::invalid("Nobody is sitting in that slot"))?;
+    *multi.host_id.write().await = host.id();
+    host.enqueue_vec(match_transfer_host()).await;
+    update(&multi, glob).await;
+    Ok(())
+}
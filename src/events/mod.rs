@@ -1,10 +1,32 @@
+mod error;
+pub use error::{ErrorKind, EventError, EventResult};
+
+use crate::Glob;
+
 pub mod change_action;
 pub mod channel_join;
 pub mod channel_part;
+pub mod channels;
+pub mod friends;
+pub mod kick;
 pub mod lobby_join;
 pub mod lobby_part;
 pub mod logout;
 pub mod matches;
 pub mod send_message;
+pub mod shutdown;
+pub mod silence;
+pub mod spectate;
 pub mod stats_request;
 pub mod status_update;
+
+/// Looks up the id of a user who doesn't have to be online
+pub(crate) async fn user_id(username: &str, glob: &Glob) -> Result<i32, EventError> {
+    let id: Option<(i32,)> = sqlx::query_as("SELECT id FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(&glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't look up user: {}", e)))?;
+    id.map(|(id,)| id)
+        .ok_or_else(|| EventError::not_found(format!("No user named {}", username)))
+}
@@ -1,32 +1,79 @@
-use crate::{bot::handle_command, event_data, packets::server::send_message, Glob, Token};
-use std::sync::Arc;
+use super::{friends, silence, spectate, user_id, EventError, EventResult};
+use crate::{
+    bot::handle_command,
+    channel::HistoryEntry,
+    events::silence::now,
+    packets::{server::send_message, OsuDecode, OsuPacket},
+    spam::SpamError,
+    Glob, Token,
+};
+use std::{
+    borrow::Cow,
+    sync::{atomic::Ordering, Arc},
+};
+use tracing::error;
 
-#[event_data]
-struct Message {
-    _na: &str, // undefined field, probably always a single null byte?
-    content: &str,
-    to: &str,
+/// Most messages a user can have waiting for people who are offline
+pub const MAX_UNDELIVERED: i64 = 50;
+
+// Public and private messages share the layout, so there's no single packet id
+#[derive(OsuPacket)]
+struct Message<'a> {
+    _na: &'a str, // undefined field, probably always a single null byte?
+    content: &'a str,
+    to: &'a str,
 }
 
-async fn common<'a>(data: &'a [u8], token: &dyn Token, glob: &Glob) -> Result<Message<'a>, String> {
-    let msg = Message::decode(data).map_err(|_| "Couldn't decode message".to_string())?;
-    if msg.content.starts_with('!') && msg.content.len() > 1 {
-        handle_command(&msg.content[1..], token, glob).await?;
+/// Returns the message together with its content after going through the spam filter
+async fn common<'a>(
+    data: &'a [u8],
+    token: &Arc<dyn Token>,
+    glob: &Glob,
+) -> Result<(Message<'a>, Cow<'a, str>), EventError> {
+    let (msg, _) =
+        Message::decode(data).map_err(|_| EventError::decode("Couldn't decode message"))?;
+    if let Some(player) = token.as_player() {
+        let remaining = player.silence_remaining();
+        if remaining > 0 {
+            return Err(EventError::permission(format!(
+                "You're silenced for {} more seconds",
+                remaining
+            )));
+        }
     }
-    Ok(msg)
+    let content = match glob.spam_filter.check(token.id(), msg.content).await {
+        Ok(content) => content,
+        Err(e @ SpamError::Flood { silence: true }) => {
+            let seconds = glob.spam_filter.flood_silence;
+            silence::silence(token.username(), seconds, "Flooding", glob.bot.as_ref(), glob)
+                .await?;
+            return Err(EventError::permission(e.to_string()));
+        }
+        Err(e @ SpamError::TooLong { .. }) => return Err(EventError::invalid(e.to_string())),
+        Err(e) => return Err(EventError::permission(e.to_string())),
+    };
+    if content.starts_with('!') && content.len() > 1 {
+        handle_command(&content[1..], token, glob).await?;
+    }
+    Ok((msg, content))
 }
 
-pub async fn public(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
-    let msg = common(data, token.as_ref(), glob).await?;
+pub async fn public(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let (msg, content) = common(data, token, glob).await?;
     let channel_name = match token.as_player() {
         Some(t) if msg.to == "#multiplayer" => {
             let multi = t.multi.lock().await;
             let multi = multi
                 .as_ref()
-                .ok_or_else(|| "You aren't in a multiplayer match".to_string())?
+                .ok_or_else(|| EventError::invalid("You aren't in a multiplayer match"))?
                 .upgrade()
-                .ok_or_else(|| "Multiplayer match has already ended")?;
-            format!("#multi_{}", multi.id)
+                .ok_or_else(|| EventError::not_found("Multiplayer match has already ended"))?;
+            multi.channel_name()
+        }
+        Some(t) if msg.to == "#spectator" => {
+            let host = t.spectating.lock().await.as_ref().and_then(|h| h.upgrade());
+            let host_id = host.map(|h| h.id()).unwrap_or_else(|| t.id);
+            spectate::channel_name(host_id)
         }
         _ => msg.to.to_string(),
     };
@@ -35,15 +82,21 @@ pub async fn public(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<
         .read()
         .await
         .get(&channel_name)
-        .ok_or_else(|| format!("No channel named {}", msg.to))?
+        .ok_or_else(|| EventError::not_found(format!("No channel named {}", msg.to)))?
         .clone();
     if !channel.has_user(token).await {
-        return Err(format!(
+        return Err(EventError::invalid(format!(
             "Tried sending message to {} before joining it",
             channel.name
-        ));
+        )));
     }
-    let packet = send_message(token.as_ref(), channel.name(), msg.content);
+    if !channel.can_write(token.as_ref()) {
+        return Err(EventError::permission(format!(
+            "You can't send messages to {}",
+            channel.name
+        )));
+    }
+    let packet = send_message(token.as_ref(), channel.name(), &content);
     for c in channel
         .users
         .read()
@@ -53,18 +106,146 @@ pub async fn public(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<
     {
         c.enqueue(&packet).await;
     }
+    channel
+        .push_history(HistoryEntry {
+            from: token.username().to_string(),
+            from_id: token.id(),
+            content: content.to_string(),
+        })
+        .await;
+    if let Err(e) = store(token.as_ref(), &channel.name, None, &content, true, glob).await {
+        error!(?e, "couldn't store message");
+    }
     Ok(())
 }
 
-pub async fn private(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> Result<(), String> {
-    let msg = common(data, token.as_ref(), glob).await?;
-    glob.token_list
+pub async fn private(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let (msg, content) = common(data, token, glob).await?;
+    let target = glob
+        .token_list
         .read()
         .await
         .values()
         .find(|t| t.username() == msg.to)
-        .ok_or_else(|| format!("{} is not active!", msg.to))?
-        .enqueue_vec(send_message(token.as_ref(), msg.to, msg.content))
-        .await;
+        .cloned();
+    let target = match target {
+        Some(target) => target,
+        None => {
+            // Kept until they log in again
+            let id = user_id(msg.to, glob).await?;
+            store_offline(token.as_ref(), msg.to, id, &content, glob).await?;
+            let reply = format!("{} is offline, they'll get your message when they log in", msg.to);
+            token
+                .enqueue_vec(send_message(glob.bot.as_ref(), token.username(), &reply))
+                .await;
+            return Ok(());
+        }
+    };
+    if let Some(p) = target.as_player() {
+        if p.block_non_friends.load(Ordering::SeqCst) && !p.is_friend(token.id()).await {
+            return Err(EventError::permission(format!(
+                "{} only accepts messages from friends",
+                msg.to
+            )));
+        }
+    }
+    target.enqueue_vec(send_message(token.as_ref(), msg.to, &content)).await;
+    let recipient = Some(target.id());
+    if let Err(e) = store(token.as_ref(), msg.to, recipient, &content, true, glob).await {
+        error!(?e, "couldn't store message");
+    }
+    Ok(())
+}
+
+async fn store(
+    from: &dyn Token,
+    target: &str,
+    recipient: Option<i32>,
+    content: &str,
+    delivered: bool,
+    glob: &Glob,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO messages (sender_id, sender_name, target, recipient_id, content, delivered,
+            created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(from.id())
+    .bind(from.username())
+    .bind(target)
+    .bind(recipient)
+    .bind(content)
+    .bind(delivered)
+    .bind(now() as i64)
+    .execute(&glob.db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Applies the same rules as for online users, using what was saved during their last session
+async fn store_offline(
+    from: &dyn Token,
+    target: &str,
+    recipient: i32,
+    content: &str,
+    glob: &Glob,
+) -> EventResult {
+    let internal = |e: sqlx::Error| EventError::internal(format!("Couldn't store message: {}", e));
+    let blocks = friends::load_block_non_friends(recipient, glob).await.map_err(internal)?;
+    if blocks && !friends::load(recipient, glob).await.map_err(internal)?.contains(&from.id()) {
+        return Err(EventError::permission(format!(
+            "{} only accepts messages from friends",
+            target
+        )));
+    }
+    let (undelivered,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM messages WHERE sender_id = $1 AND NOT delivered")
+            .bind(from.id())
+            .fetch_one(&glob.db_pool)
+            .await
+            .map_err(internal)?;
+    if undelivered >= MAX_UNDELIVERED {
+        return Err(EventError::invalid(
+            "You have too many messages waiting for people to log in",
+        ));
+    }
+    store(from, target, Some(recipient), content, false, glob).await.map_err(internal)
+}
+
+/// Loads private messages sent to the user while they were offline,
+/// together with the id of the last one to pass to `mark_delivered`
+pub async fn load_offline(
+    id: i32,
+    glob: &Glob,
+) -> Result<(Vec<HistoryEntry>, Option<i32>), sqlx::Error> {
+    let rows: Vec<(i32, i32, String, String)> = sqlx::query_as(
+        "SELECT id, sender_id, sender_name, content FROM messages
+        WHERE recipient_id = $1 AND NOT delivered ORDER BY id",
+    )
+    .bind(id)
+    .fetch_all(&glob.db_pool)
+    .await?;
+    let last = rows.last().map(|(last, ..)| *last);
+    let messages = rows
+        .into_iter()
+        .map(|(_, from_id, from, content)| HistoryEntry {
+            from,
+            from_id,
+            content,
+        })
+        .collect();
+    Ok((messages, last))
+}
+
+/// Marks the messages up to `last` as delivered, once they're in the login response
+pub async fn mark_delivered(id: i32, last: i32, glob: &Glob) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE messages SET delivered = TRUE
+        WHERE recipient_id = $1 AND NOT delivered AND id <= $2",
+    )
+    .bind(id)
+    .bind(last)
+    .execute(&glob.db_pool)
+    .await?;
     Ok(())
 }
@@ -0,0 +1,47 @@
+use super::{friends, logout};
+use crate::{
+    packets::server::{notification, server_restart},
+    Glob,
+};
+use std::{sync::atomic::Ordering, time::Duration};
+use tracing::error;
+
+/// Tells every client the server is going down and when to try reconnecting
+pub async fn announce(reconnect_after: Duration, glob: &Glob) {
+    let packet = [
+        notification("The server is restarting, you'll be reconnected shortly"),
+        server_restart(reconnect_after.as_millis() as i32),
+    ]
+    .concat();
+    for t in glob.token_list.read().await.values() {
+        t.enqueue(&packet).await;
+    }
+}
+
+/// Writes the settings which only live on the tokens, in case saving them at the time failed.
+/// Everything else gets written to the database as it happens
+pub async fn save_all(glob: &Glob) {
+    for t in glob.token_list.read().await.values() {
+        if let Some(player) = t.as_player() {
+            let block = player.block_non_friends.load(Ordering::SeqCst);
+            if let Err(e) = friends::save_block_non_friends(t.id(), block, glob).await {
+                error!(?e, id = t.id(), "couldn't save the non-friend dm setting");
+            }
+        }
+    }
+}
+
+/// Logs out everyone except for bots
+pub async fn disconnect_all(glob: &Glob) {
+    let tokens: Vec<_> = glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .filter(|t| t.as_player().is_some())
+        .map(|t| t.token().to_string())
+        .collect();
+    for token in tokens {
+        logout::handle(&token, glob).await.ok();
+    }
+}
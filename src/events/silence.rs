@@ -0,0 +1,157 @@
+use crate::{
+    audit,
+    events::{user_id, EventError, EventResult},
+    packets::server::{silence_end, user_silenced},
+    privileges::{self, Privileges},
+    Glob, Token,
+};
+use std::{
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Longest silence which can be given out, the client only takes the duration as an u32
+pub const MAX_SILENCE: u64 = 365 * 24 * 60 * 60;
+
+/// Current unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parses durations like `90`, `30s`, `10m`, `2h`, `1d` or `1w` into seconds
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let num: u64 = num.parse().ok()?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    num.checked_mul(unit)
+}
+
+async fn online(id: i32, glob: &Glob) -> Option<std::sync::Arc<dyn Token>> {
+    glob.token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.id() == id)
+        .cloned()
+}
+
+/// Looks up the target's id, only admins can act on other admins
+async fn target_id(
+    username: &str,
+    action: &str,
+    issuer: &dyn Token,
+    glob: &Glob,
+) -> Result<i32, EventError> {
+    let online = glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.username() == username)
+        .cloned();
+    let (id, target) = match online {
+        Some(t) => (t.id(), t.privileges()),
+        None => {
+            let id = user_id(username, glob).await?;
+            let target = privileges::load(id, glob)
+                .await
+                .map_err(|e| EventError::internal(format!("Couldn't load privileges: {}", e)))?;
+            (id, target)
+        }
+    };
+    if target.has(Privileges::ADMIN) && !issuer.privileges().has(Privileges::ADMIN) {
+        return Err(EventError::permission(format!("You can't {} an admin", action)));
+    }
+    Ok(id)
+}
+
+/// Silences the user for the given amount of seconds, even if they're offline
+pub async fn silence(
+    username: &str,
+    seconds: u64,
+    reason: &str,
+    issuer: &dyn Token,
+    glob: &Glob,
+) -> EventResult {
+    if seconds > MAX_SILENCE {
+        return Err(EventError::invalid("Silences can't be longer than a year"));
+    }
+    let now = now();
+    let end = now
+        .checked_add(seconds)
+        .ok_or_else(|| EventError::invalid("Silence would never end"))?;
+    let id = target_id(username, "silence", issuer, glob).await?;
+    sqlx::query(
+        "INSERT INTO silences (user_id, issuer_id, reason, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(id)
+    .bind(issuer.id())
+    .bind(reason)
+    .bind(now as i64)
+    .bind(end as i64)
+    .execute(&glob.db_pool)
+    .await
+    .map_err(|e| EventError::internal(format!("Couldn't silence user: {}", e)))?;
+    audit::log(issuer.id(), id, "silence", reason, glob)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't write to the audit log: {}", e)))?;
+
+    if let Some(target) = online(id, glob).await {
+        if let Some(player) = target.as_player() {
+            player.silence_end.store(end, Ordering::SeqCst);
+        }
+        target.enqueue_vec(silence_end(seconds as u32)).await;
+    }
+    let packet = user_silenced(id);
+    for t in glob.token_list.read().await.values().filter(|t| t.id() != id) {
+        t.enqueue(&packet).await;
+    }
+    Ok(())
+}
+
+/// Ends all of the user's silences early
+pub async fn unsilence(username: &str, issuer: &dyn Token, glob: &Glob) -> EventResult {
+    let id = target_id(username, "unsilence", issuer, glob).await?;
+    let now = now() as i64;
+    sqlx::query("UPDATE silences SET expires_at = $2 WHERE user_id = $1 AND expires_at > $2")
+        .bind(id)
+        .bind(now)
+        .execute(&glob.db_pool)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't unsilence user: {}", e)))?;
+    audit::log(issuer.id(), id, "unsilence", "", glob)
+        .await
+        .map_err(|e| EventError::internal(format!("Couldn't write to the audit log: {}", e)))?;
+
+    if let Some(target) = online(id, glob).await {
+        if let Some(player) = target.as_player() {
+            player.silence_end.store(0, Ordering::SeqCst);
+        }
+        target.enqueue_vec(silence_end(0)).await;
+    }
+    Ok(())
+}
+
+/// Loads the unix time at which the user's last silence ends
+pub async fn load(id: i32, glob: &Glob) -> Result<u64, sqlx::Error> {
+    let (end,): (Option<i64>,) =
+        sqlx::query_as("SELECT MAX(expires_at) FROM silences WHERE user_id = $1")
+            .bind(id)
+            .fetch_one(&glob.db_pool)
+            .await?;
+    Ok(end.unwrap_or(0) as u64)
+}
@@ -0,0 +1,160 @@
+use super::{EventError, EventResult};
+use crate::{
+    packets::{
+        server::{
+            channel_join_success, channel_kicked, fellow_spectator_joined, fellow_spectator_left,
+            spectate_frames, spectator_cant_spectate, spectator_joined, spectator_left,
+        },
+        OsuDecode,
+    },
+    Channel, Glob, Token,
+};
+use std::sync::Arc;
+use tracing::instrument;
+
+pub fn channel_name(host_id: i32) -> String { format!("#spect_{}", host_id) }
+
+#[instrument(skip(data, token, glob), target = "start_spectating")]
+pub async fn start(data: &[u8], token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let (host_id, _) =
+        i32::decode(data).map_err(|_| EventError::decode("Couldn't decode host id"))?;
+    if host_id == token.id() {
+        return Err(EventError::invalid("You can't spectate yourself"));
+    }
+    let host = glob
+        .token_list
+        .read()
+        .await
+        .values()
+        .find(|t| t.id() == host_id)
+        .cloned()
+        .ok_or_else(|| EventError::not_found(format!("User {} is not online", host_id)))?;
+    let host_player = host
+        .as_player()
+        .ok_or_else(|| EventError::invalid("You can't spectate a bot"))?;
+
+    let old = player.spectating.lock().await.take().and_then(|h| h.upgrade());
+    if let Some(old) = old {
+        leave(&old, token, glob).await;
+    }
+
+    let fellows = {
+        let mut spectators = host_player.spectators.write().await;
+        let fellows = spectators.clone();
+        spectators.push(token.clone());
+        fellows
+    };
+    *player.spectating.lock().await = Some(Arc::downgrade(&host));
+
+    let (ch, created) = {
+        let mut list = glob.channel_list.write().await;
+        let name = channel_name(host_id);
+        let existing = list.get(&name).cloned();
+        match existing {
+            Some(ch) => (ch, false),
+            None => (Channel::new(&mut list, &name, "", false), true),
+        }
+    };
+    if created && ch.user_join(host.clone()).await {
+        host.join_channel(Arc::downgrade(&ch)).await;
+        host.enqueue_vec(channel_join_success(&ch)).await;
+    }
+    if ch.user_join(token.clone()).await {
+        token.join_channel(Arc::downgrade(&ch)).await;
+        token.enqueue_vec(channel_join_success(&ch)).await;
+    }
+
+    host.enqueue_vec(spectator_joined(token.id())).await;
+    let packet = fellow_spectator_joined(token.id());
+    for f in fellows.iter() {
+        f.enqueue(&packet).await;
+        token.enqueue_vec(fellow_spectator_joined(f.id())).await;
+    }
+    Ok(())
+}
+
+#[instrument(skip(token, glob), target = "stop_spectating")]
+pub async fn stop(token: &Arc<dyn Token>, glob: &Glob) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let host = player
+        .spectating
+        .lock()
+        .await
+        .take()
+        .and_then(|h| h.upgrade())
+        .ok_or_else(|| EventError::invalid("You aren't spectating anyone"))?;
+    leave(&host, token, glob).await;
+    Ok(())
+}
+
+/// Removes the spectator from the host's list and
+/// disposes of the spectator channel once nobody is watching
+pub(crate) async fn leave(host: &Arc<dyn Token>, spectator: &Arc<dyn Token>, glob: &Glob) {
+    let host_player = match host.as_player() {
+        Some(p) => p,
+        None => return,
+    };
+    let (fellows, empty) = {
+        let mut spectators = host_player.spectators.write().await;
+        if let Some(pos) = spectators.iter().position(|t| Arc::ptr_eq(t, spectator)) {
+            spectators.remove(pos);
+        }
+        (spectators.clone(), spectators.is_empty())
+    };
+
+    let name = channel_name(host.id());
+    let ch = glob.channel_list.read().await.get(&name).cloned();
+    if let Some(ch) = ch.as_ref() {
+        if ch.user_part(spectator).await {
+            spectator.part_channel(ch).await;
+            spectator.enqueue_vec(channel_kicked(ch)).await;
+        }
+    }
+
+    host.enqueue_vec(spectator_left(spectator.id())).await;
+    let packet = fellow_spectator_left(spectator.id());
+    for f in fellows.iter() {
+        f.enqueue(&packet).await;
+    }
+
+    if empty {
+        let ch = glob.channel_list.write().await.remove(&name);
+        if let Some(ch) = ch {
+            if ch.user_part(host).await {
+                host.part_channel(&ch).await;
+                host.enqueue_vec(channel_kicked(&ch)).await;
+            }
+        }
+    }
+}
+
+pub async fn frames(data: &[u8], token: &dyn Token) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let packet = spectate_frames(data);
+    for s in player.spectators.read().await.iter() {
+        s.enqueue(&packet).await;
+    }
+    Ok(())
+}
+
+pub async fn cant_spectate(token: &dyn Token) -> EventResult {
+    let player = token.as_player().ok_or_else(|| EventError::permission("You're a bot"))?;
+    let host = player
+        .spectating
+        .lock()
+        .await
+        .as_ref()
+        .and_then(|h| h.upgrade())
+        .ok_or_else(|| EventError::invalid("You aren't spectating anyone"))?;
+    let packet = spectator_cant_spectate(token.id());
+    host.enqueue(&packet).await;
+    if let Some(host) = host.as_player() {
+        for s in host.spectators.read().await.iter() {
+            if s.id() != token.id() {
+                s.enqueue(&packet).await;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,13 @@
 use crate::{
-    packets::{server::user_stats, OsuEncode},
+    events::{EventError, EventResult},
+    packets::{server::user_stats, OsuDecode},
     token::Token,
     Glob,
 };
 
-pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> Result<(), String> {
-    let (users, _) = <[i32]>::decode(data).map_err(|_| "Couldn't decode data".to_string())?;
+pub async fn handle(data: &[u8], token: &dyn Token, glob: &Glob) -> EventResult {
+    let (users, _) =
+        Vec::<i32>::decode(data).map_err(|_| EventError::decode("Couldn't decode data"))?;
     let mut res = Vec::new();
     for t in glob
         .token_list
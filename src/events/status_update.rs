@@ -1,7 +1,47 @@
-use crate::{packets::server::user_stats, Token};
+use crate::{
+    events::{EventError, EventResult},
+    packets::server::user_stats,
+    token::player::{GameMode, ModeStats},
+    Glob, Token,
+};
+use std::convert::TryFrom;
 
-pub async fn handle(token: &dyn Token) -> Result<(), String> {
-    // TODO: Actually update the stats, lol
+pub async fn handle(token: &dyn Token, glob: &Glob) -> EventResult {
+    if let Some(player) = token.as_player() {
+        let stats = load(token.id(), glob)
+            .await
+            .map_err(|e| EventError::internal(format!("Couldn't load stats: {}", e)))?;
+        player.update_mode_stats(stats).await;
+    }
     token.enqueue_vec(user_stats(token).await).await;
     Ok(())
 }
+
+/// Loads the user's stats for every game mode from the database,
+/// ranking them by pp among everyone playing that mode
+pub async fn load(id: i32, glob: &Glob) -> Result<[ModeStats; 4], sqlx::Error> {
+    let rows: Vec<(i16, i64, i64, f32, i32, i32, i64)> = sqlx::query_as(
+        "SELECT s.game_mode, s.ranked_score, s.total_score, s.accuracy, s.playcount, s.pp,
+            (SELECT COUNT(*) + 1 FROM user_stats o WHERE o.game_mode = s.game_mode AND o.pp > s.pp)
+        FROM user_stats s WHERE s.user_id = $1",
+    )
+    .bind(id)
+    .fetch_all(&glob.db_pool)
+    .await?;
+    let mut res = [ModeStats::default(); 4];
+    for (mode, ranked_score, total_score, accuracy, playcount, pp, rank) in rows {
+        let mode = match GameMode::try_from(mode as u8) {
+            Ok(mode) => mode,
+            Err(_) => continue,
+        };
+        res[mode as usize] = ModeStats {
+            ranked_score: ranked_score as u64,
+            accuracy,
+            playcount: playcount as u32,
+            total_score: total_score as u64,
+            rank: rank as u32,
+            pp: u16::try_from(pp.max(0)).unwrap_or(u16::MAX),
+        };
+    }
+    Ok(res)
+}
@@ -3,11 +3,13 @@
 use futures::{future::join_all, stream::TryStreamExt};
 use hyper::{Body, Request, Response};
 use sqlx::{postgres::PgPool, prelude::*};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{atomic::Ordering, Arc},
+};
 use tokio::sync::RwLock;
-use tracing::{error, instrument, trace};
-
-pub use isoku_macros::event_data;
+use tracing::{debug, error, instrument, trace};
 
 #[macro_use]
 pub mod packets;
@@ -18,6 +20,13 @@ pub mod channel;
 pub use channel::Channel;
 pub mod bot;
 pub mod events;
+pub mod audit;
+pub mod limiter;
+pub mod login;
+pub mod privileges;
+pub mod shutdown;
+pub mod spam;
+use login::{LoginInfo, LoginLimiter};
 pub mod r#match;
 pub use r#match::Match;
 
@@ -26,22 +35,30 @@ use token::Token;
 const PROTOCOL_VERSION: u32 = 19;
 
 #[derive(Debug)]
+/// Everyone browsing the multiplayer lobby
+pub type Lobby = RwLock<Vec<Arc<dyn Token>>>;
+
 pub struct Glob {
     pub db_pool: PgPool,
     pub token_list: RwLock<HashMap<String, Arc<dyn Token>>>,
     pub channel_list: RwLock<HashMap<String, Arc<Channel>>>,
-    pub match_list: RwLock<HashMap<u16, Arc<Match>>>,
-    pub lobby: RwLock<Vec<Arc<dyn Token>>>,
+    pub match_list: RwLock<r#match::MatchList>,
+    pub lobby: Arc<Lobby>,
     pub bot: Arc<dyn Token>,
+    pub login_limiter: LoginLimiter,
+    pub commands: bot::Commands,
+    pub spam_filter: spam::SpamFilter,
+    pub shutdown: shutdown::Shutdown,
 }
 
 impl Glob {
     pub async fn new() -> Self {
         let db_url = std::env::var("DATABASE_URL").unwrap();
         let db_pool = PgPool::new(&db_url).await.unwrap();
-        let mut channel_list = HashMap::with_capacity(2);
-        Channel::new(&mut channel_list, "#osu", "wojexe to ciota", true);
-        Channel::new(&mut channel_list, "#lobby", "multi", true);
+        let mut channel_list = HashMap::new();
+        for def in channel::ChannelDef::load_all(&db_pool).await.unwrap() {
+            Channel::from_def(&mut channel_list, def);
+        }
         let mut token_list = HashMap::with_capacity(2);
         let bot = DummyToken::new(&mut token_list, 3, "kenshichi".to_string());
         for ch in channel_list.values() {
@@ -50,7 +67,7 @@ impl Glob {
         let token_list = RwLock::new(token_list);
         let channel_list = RwLock::new(channel_list);
         let match_list = RwLock::default();
-        let lobby = RwLock::default();
+        let lobby = Arc::default();
         Glob {
             db_pool,
             token_list,
@@ -58,36 +75,91 @@ impl Glob {
             match_list,
             lobby,
             bot,
+            login_limiter: LoginLimiter::from_env(),
+            commands: bot::Commands::builtin(),
+            spam_filter: spam::SpamFilter::from_env(),
+            shutdown: shutdown::Shutdown::default(),
         }
     }
 }
 
-#[instrument(skip(glob))]
-async fn login(body: &[u8], glob: Arc<Glob>) -> Result<(String, Vec<u8>), &'static str> {
-    let (username, password) = {
-        let login_data = std::str::from_utf8(body).map_err(|_| "Bad request")?;
+#[instrument(skip(body, glob))]
+async fn login(
+    body: &[u8],
+    ip: IpAddr,
+    glob: Arc<Glob>,
+) -> Result<(String, Vec<u8>), &'static str> {
+    // Shutdown waits for this to be dropped
+    let _request = match glob.shutdown.request() {
+        Some(request) => request,
+        None => return Err("The server is restarting, try again in a moment"),
+    };
+    let login_data = std::str::from_utf8(body).map_err(|_| "Bad request")?;
+    let (username, password, info) = {
         let mut login_data = login_data.split('\n');
         let username = login_data.next().ok_or("Bad request")?;
         let password = login_data.next().ok_or("Bad request")?;
-        (username.trim(), password)
+        let info = login_data.next().and_then(LoginInfo::parse).ok_or("Bad request")?;
+        (username.trim(), password.trim(), info)
     };
     if username == "wojexe" {
         return Err("wojexe to ciota");
     }
-    let id: Option<(i32,)> =
-        sqlx::query_as("SELECT id FROM users WHERE username = $1 AND password = $2")
+    if !login::is_md5(password) {
+        return Err("Bad request");
+    }
+    if glob.login_limiter.is_locked(ip, username).await {
+        return Err("Too many failed login attempts, try again later");
+    }
+    debug!(version = info.version, utc_offset = info.utc_offset, "login attempt");
+    let user: Option<(i32, String)> =
+        sqlx::query_as("SELECT id, password FROM users WHERE username = $1")
             .bind(username)
-            .bind(password)
             .fetch_optional(&glob.db_pool)
             .await
-            .unwrap();
-    let id = match id {
-        Some((id,)) => id,
-        None => return Err("User doesn't exist"),
+            .map_err(|e| {
+                error!(?e, "couldn't fetch user");
+                "Couldn't log in"
+            })?;
+    let hash = user.as_ref().map(|(_, hash)| hash.as_str());
+    let id = match user {
+        Some((id, _)) if login::verify_password(password, hash).await => id,
+        Some(_) => {
+            glob.login_limiter.failed(ip, username).await;
+            return Err("Wrong username or password");
+        }
+        None => {
+            // Same amount of work as for a wrong password
+            login::verify_password(password, None).await;
+            glob.login_limiter.failed(ip, username).await;
+            return Err("Wrong username or password");
+        }
     };
+    glob.login_limiter.succeeded(username).await;
     if glob.token_list.read().await.values().any(|t| t.id() == id) {
         return Err("Already logged in?");
     }
+    let friends = events::friends::load(id, &glob).await.map_err(|e| {
+        error!(?e, "couldn't load friends");
+        "Couldn't load your friends list"
+    })?;
+    let stats = events::status_update::load(id, &glob).await.map_err(|e| {
+        error!(?e, "couldn't load stats");
+        "Couldn't load your stats"
+    })?;
+    let silence_end = events::silence::load(id, &glob).await.map_err(|e| {
+        error!(?e, "couldn't load silences");
+        "Couldn't load your silences"
+    })?;
+    let privileges = privileges::load(id, &glob).await.map_err(|e| {
+        error!(?e, "couldn't load privileges");
+        "Couldn't load your privileges"
+    })?;
+    let (offline_messages, last_offline) =
+        events::send_message::load_offline(id, &glob).await.map_err(|e| {
+            error!(?e, "couldn't load offline messages");
+            "Couldn't load your messages"
+        })?;
     let token = {
         // let mut list = glob.token_list.write().await;
         // Token::new(&mut list, id, username.to_string())
@@ -99,6 +171,18 @@ async fn login(body: &[u8], glob: Arc<Glob>) -> Result<(String, Vec<u8>), &'stat
         )
         .await
     };
+    if let Some(player) = token.as_player() {
+        *player.friends.write().await = friends.clone();
+        player.update_mode_stats(stats).await;
+        player.block_non_friends.store(info.block_non_friends, Ordering::SeqCst);
+        let block = info.block_non_friends;
+        if let Err(e) = events::friends::save_block_non_friends(id, block, &glob).await {
+            error!(?e, "couldn't save the non-friend dm setting");
+        }
+        player.silence_end.store(silence_end, Ordering::SeqCst);
+        player.privileges.store(privileges.0, Ordering::SeqCst);
+        *player.undelivered.lock().await = last_offline;
+    }
     let online: Vec<i32> = glob
         .token_list
         .read()
@@ -106,14 +190,32 @@ async fn login(body: &[u8], glob: Arc<Glob>) -> Result<(String, Vec<u8>), &'stat
         .values()
         .map(|t| t.id())
         .collect();
-    let channels = join_all(
-        glob.channel_list
-            .read()
-            .await
-            .values()
-            .filter(|ch| ch.public)
-            .map(|ch| p::channel_info(ch)),
-    )
+    let channels: Vec<_> = glob
+        .channel_list
+        .read()
+        .await
+        .values()
+        .filter(|ch| ch.public && ch.can_read(token.as_ref()))
+        .cloned()
+        .collect();
+    let mut joined = Vec::new();
+    for ch in channels.iter().filter(|ch| ch.auto_join) {
+        if ch.user_join(token.clone()).await {
+            token.join_channel(Arc::downgrade(ch)).await;
+            joined.push(p::channel_join_success(ch));
+            for entry in ch.history().await {
+                let packet = p::message_from(&entry.from, entry.from_id, ch.name(), &entry.content);
+                joined.push(packet);
+            }
+        }
+    }
+    let channels = join_all(channels.iter().map(|ch| async move {
+        if ch.auto_join {
+            p::channel_auto_join(ch).await
+        } else {
+            p::channel_info(ch).await
+        }
+    }))
     .await;
     let token_list = glob
         .token_list
@@ -124,17 +226,22 @@ async fn login(body: &[u8], glob: Arc<Glob>) -> Result<(String, Vec<u8>), &'stat
         .collect();
     let user_stats = p::user_stats(token.as_ref()).await;
     let data = [
-        p::silence_end(0),
+        p::silence_end(silence_end.saturating_sub(events::silence::now()) as u32),
         p::protocol_ver(PROTOCOL_VERSION),
         p::user_id(token.id()),
         p::user_rank(0),
-        p::friend_list(&[]),
+        p::friend_list(&friends),
         p::user_panel(token.as_ref()),
         user_stats,
         p::online_users(&online),
         token_list,
         p::channel_info_end(),
         channels.into_iter().flatten().collect(),
+        joined.into_iter().flatten().collect(),
+        offline_messages
+            .iter()
+            .flat_map(|m| p::message_from(&m.from, m.from_id, username, &m.content))
+            .collect(),
     ]
     .concat();
     Ok((token.token().to_owned(), data))
@@ -150,14 +257,35 @@ async fn handle_packet(
         Some(t) => t.clone(),
         None => return Err("Wrong token"),
     };
+    // Shutdown waits for this to be dropped
+    let _request = match glob.shutdown.request() {
+        Some(request) => request,
+        None => {
+            // Polls still pick up the restart announcement, but don't hold up the shutdown
+            return Ok((token.token().to_owned(), token.clear_queue().await));
+        }
+    };
+    if token.as_player().map_or(false, |p| p.kicked.load(Ordering::SeqCst)) {
+        // Whatever they sent doesn't matter anymore, they only need to hear why they're gone
+        let res = token.clear_queue().await;
+        events::logout::handle(token.token(), &glob).await.ok();
+        return Ok((token.token().to_owned(), res));
+    }
+    if let Some(player) = token.as_player() {
+        if let Some(last) = player.undelivered.lock().await.take() {
+            if let Err(e) = events::send_message::mark_delivered(player.id, last, &glob).await {
+                error!(?e, "couldn't mark offline messages as delivered");
+            }
+        }
+    }
     trace!("handling packet");
     let mut res = Vec::new();
     use packets::Id;
-    while body.len() >= 7 {
+    while body.len() >= packets::HEADER_LEN {
         let (id, len) = packets::parse_packet(body).map_err(|_| "Couldn't parse packet")?;
-        let (data, rest) = (&body[7..]).split_at(len);
+        let (data, rest) = (&body[packets::HEADER_LEN..]).split_at(len);
         body = rest;
-        let packet: Result<(), String> = match id {
+        let packet: events::EventResult = match id {
             Id::Unknown => {
                 println!("UNKNOWN ID!");
                 continue;
@@ -173,7 +301,7 @@ async fn handle_packet(
                             events::logout::handle(token.token(), &glob)
                                 .await
                                 .map_err(|e| {
-                                    error!(e);
+                                    error!(%e);
                                 })
                                 .ok();
                         }
@@ -184,31 +312,79 @@ async fn handle_packet(
             Id::UserStatsRequest => {
                 events::stats_request::handle(data, token.as_ref(), &glob).await
             }
-            Id::RequestStatusUpdate => events::status_update::handle(token.as_ref()).await,
+            Id::RequestStatusUpdate => events::status_update::handle(token.as_ref(), &glob).await,
             Id::CreateMatch => events::matches::create(data, &token, &glob).await,
             Id::MatchChangeSettings => {
                 events::matches::change_settings(data, token.as_ref(), &glob).await
             }
+            Id::MatchJoin => events::matches::join(data, &token, &glob).await,
+            Id::MatchPart => events::matches::part(&token, &glob).await,
+            Id::MatchReady => events::matches::ready(token.as_ref(), &glob).await,
+            Id::MatchNotReady => events::matches::not_ready(token.as_ref(), &glob).await,
+            Id::MatchStart => events::matches::start(token.as_ref(), &glob).await,
+            Id::MatchLoadComplete => events::matches::load_complete(token.as_ref()).await,
+            Id::MatchSkipRequest => events::matches::skip(token.as_ref()).await,
+            Id::MatchScoreUpdate => events::matches::score_update(data, token.as_ref()).await,
+            Id::MatchComplete => events::matches::complete(token.as_ref(), &glob).await,
+            Id::MatchFailed => events::matches::failed(token.as_ref()).await,
+            Id::MatchTransferHost => {
+                events::matches::transfer_host(data, token.as_ref(), &glob).await
+            }
+            Id::MatchChangeSlot => events::matches::change_slot(data, token.as_ref(), &glob).await,
+            Id::MatchLock => events::matches::lock(data, token.as_ref(), &glob).await,
+            Id::MatchChangeTeam => events::matches::change_team(token.as_ref(), &glob).await,
+            Id::MatchChangeMods => events::matches::change_mods(data, token.as_ref(), &glob).await,
+            Id::MatchInvite => events::matches::invite(data, token.as_ref(), &glob).await,
+            Id::MatchChangePassword => {
+                events::matches::change_password(data, token.as_ref(), &glob).await
+            }
+            Id::StartSpectating => events::spectate::start(data, &token, &glob).await,
+            Id::StopSpectating => events::spectate::stop(&token, &glob).await,
+            Id::SpectateFrames => events::spectate::frames(data, token.as_ref()).await,
+            Id::CantSpectate => events::spectate::cant_spectate(token.as_ref()).await,
+            Id::FriendAdd => events::friends::add(data, token.as_ref(), &glob).await,
+            Id::FriendRemove => events::friends::remove(data, token.as_ref(), &glob).await,
+            Id::ToggleBlockNonFriendDms => {
+                events::friends::toggle_block_non_friends(data, token.as_ref(), &glob).await
+            }
             Id::JoinLobby => events::lobby_join::handle(&token, &glob).await,
             Id::PartLobby => events::lobby_part::handle(&token, &glob).await,
             Id::ChannelJoin => events::channel_join::handle(data, &token, &glob).await,
             Id::ChannelPart => events::channel_part::handle(data, &token, &glob).await,
             Id::ChangeAction => events::change_action::handle(data, token.as_ref(), &glob).await,
-            Id::Logout => {
-                events::logout::handle(token.token(), &glob).await?;
-                break;
-            }
-            _ => Err(format!("Unhandled packet {:?}", id)),
+            Id::Logout => match events::logout::handle(token.token(), &glob).await {
+                Ok(()) => break,
+                Err(e) => Err(e),
+            },
+            _ => Err(events::EventError::not_found(format!(
+                "Unhandled packet {:?}",
+                id
+            ))),
         };
-        if let Err(msg) = packet {
-            res.append(&mut p::notification(&msg));
+        if let Err(e) = packet {
+            if e.kind.is_error() {
+                error!(%e, ?id, "event failed");
+            } else {
+                debug!(%e, ?id, "event failed");
+            }
+            if e.kind.notifies() {
+                res.append(&mut p::notification(e.client_message()));
+            }
+            if e.kind.drops_session() {
+                events::logout::handle(token.token(), &glob).await.ok();
+                return Err("Your session has been dropped");
+            }
         }
     }
     res.append(&mut token.clear_queue().await);
     Ok((token.token().to_owned(), res))
 }
 
-pub async fn main_handler(req: Request<Body>, glob: Arc<Glob>) -> http::Result<Response<Body>> {
+pub async fn main_handler(
+    req: Request<Body>,
+    remote_addr: IpAddr,
+    glob: Arc<Glob>,
+) -> http::Result<Response<Body>> {
     let (parts, body) = req.into_parts();
     let body: Vec<u8> = body
         .map_ok(|chunk| chunk.to_vec())
@@ -216,7 +392,10 @@ pub async fn main_handler(req: Request<Body>, glob: Arc<Glob>) -> http::Result<R
         .await
         .unwrap();
     let res = match parts.headers.get("osu-token") {
-        None => login(&body, glob).await,
+        None => {
+            let ip = glob.login_limiter.client_ip(&parts.headers, remote_addr);
+            login(&body, ip, glob).await
+        }
         Some(token) => {
             let token = match token.to_str() {
                 Ok(token) => token,
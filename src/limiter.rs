@@ -0,0 +1,68 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Counts hits per key and says when there were too many of them in a window of time
+#[derive(Debug)]
+pub struct RateLimiter<K: Eq + Hash> {
+    pub max_hits: usize,
+    pub window: Duration,
+    hits: Mutex<HashMap<K, VecDeque<Instant>>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(max_hits: usize, window: Duration) -> Self {
+        RateLimiter {
+            max_hits,
+            window,
+            hits: Mutex::default(),
+        }
+    }
+
+    fn prune(hits: &mut VecDeque<Instant>, window: Duration) {
+        let now = Instant::now();
+        while hits.front().map_or(false, |t| now.duration_since(*t) > window) {
+            hits.pop_front();
+        }
+    }
+
+    /// Whether the key has already reached the limit
+    pub async fn is_limited(&self, key: &K) -> bool {
+        let mut list = self.hits.lock().await;
+        match list.get_mut(key) {
+            Some(hits) => {
+                Self::prune(hits, self.window);
+                if hits.is_empty() {
+                    list.remove(key);
+                    false
+                } else {
+                    hits.len() >= self.max_hits
+                }
+            }
+            None => false,
+        }
+    }
+
+    /// Records a hit, returns true if it made the key reach the limit
+    pub async fn hit(&self, key: K) -> bool {
+        let mut list = self.hits.lock().await;
+        let hits = list.entry(key).or_default();
+        Self::prune(hits, self.window);
+        hits.push_back(Instant::now());
+        hits.len() == self.max_hits
+    }
+
+    pub async fn reset(&self, key: &K) { self.hits.lock().await.remove(key); }
+
+    /// Forgets keys whose hits are all older than the window
+    pub async fn cleanup(&self) {
+        let window = self.window;
+        self.hits.lock().await.retain(|_, hits| {
+            Self::prune(hits, window);
+            !hits.is_empty()
+        });
+    }
+}
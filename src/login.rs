@@ -0,0 +1,177 @@
+use crate::limiter::RateLimiter;
+use http::HeaderMap;
+use lazy_static::lazy_static;
+use std::{net::IpAddr, time::Duration};
+use tracing::{error, warn};
+
+lazy_static! {
+    /// Unknown users get checked against this so that the response takes
+    /// as long as for a wrong password and doesn't reveal which usernames exist.
+    /// `BCRYPT_COST` has to match the cost the stored hashes were made with
+    static ref DUMMY_HASH: String = {
+        let cost = std::env::var("BCRYPT_COST")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(bcrypt::DEFAULT_COST);
+        bcrypt::hash("00000000000000000000000000000000", cost).unwrap()
+    };
+}
+
+/// Hashes the dummy password ahead of time, so the first unknown user doesn't pay for it
+pub async fn init_dummy_hash() {
+    if let Err(e) = tokio::task::spawn_blocking(|| lazy_static::initialize(&DUMMY_HASH)).await {
+        error!(?e, "couldn't hash the dummy password");
+    }
+}
+
+/// Hashes of the client's hardware and installation, separated by `:`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientHashes<'a> {
+    pub osu_path: &'a str,
+    pub adapters: &'a str,
+    pub adapters_md5: &'a str,
+    pub uninstall_id: &'a str,
+    pub disk_signature: &'a str,
+}
+
+impl<'a> ClientHashes<'a> {
+    pub fn parse(hashes: &'a str) -> Option<Self> {
+        let mut hashes = hashes.split(':');
+        Some(ClientHashes {
+            osu_path: hashes.next()?,
+            adapters: hashes.next()?,
+            adapters_md5: hashes.next()?,
+            uninstall_id: hashes.next()?,
+            disk_signature: hashes.next()?,
+        })
+    }
+}
+
+/// Third line of the login request, in the form of
+/// `version|utc_offset|display_city|hashes|block_non_friend_dms`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginInfo<'a> {
+    pub version: &'a str,
+    pub utc_offset: i8,
+    pub display_city: bool,
+    pub hashes: ClientHashes<'a>,
+    pub block_non_friends: bool,
+}
+
+impl<'a> LoginInfo<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let mut info = line.trim().split('|');
+        Some(LoginInfo {
+            version: info.next()?,
+            utc_offset: info.next()?.parse().ok()?,
+            display_city: info.next()? == "1",
+            hashes: ClientHashes::parse(info.next()?)?,
+            block_non_friends: info.next()? == "1",
+        })
+    }
+}
+
+/// The client never sends the password itself, only its md5 as a hex string
+pub fn is_md5(password: &str) -> bool {
+    password.len() == 32 && password.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Checks the md5 of the password against the stored bcrypt hash.
+/// When there's no user the check still runs against a dummy hash
+pub async fn verify_password(password_md5: &str, hash: Option<&str>) -> bool {
+    let exists = hash.is_some();
+    let password_md5 = password_md5.to_ascii_lowercase();
+    let hash = hash.map(str::to_string);
+    // bcrypt is slow on purpose, so don't block the other requests with it
+    let res = tokio::task::spawn_blocking(move || {
+        bcrypt::verify(&password_md5, hash.as_deref().unwrap_or(DUMMY_HASH.as_str()))
+    })
+    .await;
+    match res {
+        Ok(Ok(valid)) => exists && valid,
+        Ok(Err(e)) => {
+            error!(?e, "couldn't verify password hash");
+            false
+        }
+        Err(e) => {
+            error!(?e, "password verification panicked");
+            false
+        }
+    }
+}
+
+/// Failed login attempts, counted both per IP and per username
+#[derive(Debug)]
+pub struct LoginLimiter {
+    pub by_ip: RateLimiter<IpAddr>,
+    pub by_username: RateLimiter<String>,
+    /// Header in which a reverse proxy passes the client's address, like `X-Forwarded-For`.
+    /// Without it every client behind the proxy would share the proxy's address
+    pub ip_header: Option<String>,
+}
+
+impl LoginLimiter {
+    pub fn new(max_failures: usize, window: Duration) -> Self {
+        LoginLimiter {
+            by_ip: RateLimiter::new(max_failures, window),
+            by_username: RateLimiter::new(max_failures, window),
+            ip_header: None,
+        }
+    }
+
+    /// Reads `LOGIN_MAX_FAILURES` and `LOGIN_FAILURE_WINDOW` (in seconds),
+    /// defaulting to 5 failures in 5 minutes, and `LOGIN_IP_HEADER`.
+    /// Only set the header when running behind a proxy which overwrites it,
+    /// otherwise clients can pick whatever address they want
+    pub fn from_env() -> Self {
+        let max_failures = std::env::var("LOGIN_MAX_FAILURES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5);
+        let window = std::env::var("LOGIN_FAILURE_WINDOW")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5 * 60);
+        LoginLimiter {
+            ip_header: std::env::var("LOGIN_IP_HEADER").ok(),
+            ..LoginLimiter::new(max_failures, Duration::from_secs(window))
+        }
+    }
+
+    /// Address failures get counted for, the proxy appends the address it saw
+    /// so only the last one in the header can be trusted
+    pub fn client_ip(&self, headers: &HeaderMap, remote_addr: IpAddr) -> IpAddr {
+        let header = match &self.ip_header {
+            Some(header) => header,
+            None => return remote_addr,
+        };
+        headers
+            .get(header.as_str())
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(remote_addr)
+    }
+
+    pub async fn is_locked(&self, ip: IpAddr, username: &str) -> bool {
+        self.by_ip.is_limited(&ip).await
+            || self.by_username.is_limited(&username.to_lowercase()).await
+    }
+
+    pub async fn failed(&self, ip: IpAddr, username: &str) {
+        if self.by_ip.hit(ip).await {
+            warn!(%ip, "too many failed logins, locking out ip");
+        }
+        if self.by_username.hit(username.to_lowercase()).await {
+            warn!(username, "too many failed logins, locking out username");
+        }
+        self.by_ip.cleanup().await;
+        self.by_username.cleanup().await;
+    }
+
+    /// Only the username gets cleared, so one working account
+    /// can't be used to keep guessing passwords of others
+    pub async fn succeeded(&self, username: &str) {
+        self.by_username.reset(&username.to_lowercase()).await;
+    }
+}
@@ -5,10 +5,11 @@ use hyper::{
     Body, Method, Request, Response, Server,
 };
 use std::{net::SocketAddr, sync::Arc};
+use tokio::signal::unix::{signal, SignalKind};
 use tracing::{instrument, trace, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use isoku::{main_handler, Glob};
+use isoku::{events, login, main_handler, shutdown, spam, Glob};
 
 const EASTER: &str = "<pre>
                     __        
@@ -26,7 +27,7 @@ async fn handle_request(
     glob: Arc<Glob>,
 ) -> http::Result<Response<Body>> {
     match (req.method(), req.uri().path()) {
-        (&Method::POST, "/") => main_handler(req, glob).await,
+        (&Method::POST, "/") => main_handler(req, remote_addr.ip(), glob).await,
         (&Method::GET, "/") => Response::builder().status(200).body(EASTER.into()),
         (&Method::POST, p) if p.starts_with("/api") => {
             Response::builder().status(200).body("API soon(tm)".into())
@@ -38,6 +39,15 @@ async fn handle_request(
     }
 }
 
+/// Resolves on the first SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Couldn't listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
@@ -47,10 +57,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
     let glob = Arc::new(Glob::new().await);
+    login::init_dummy_hash().await;
+    tokio::spawn(events::channels::update_counts_forever(glob.clone()));
+    tokio::spawn(spam::cleanup_forever(glob.clone()));
 
+    let service_glob = glob.clone();
     let service = make_service_fn(move |socket: &AddrStream| {
         let remote_addr = socket.remote_addr();
-        let glob = glob.clone();
+        let glob = service_glob.clone();
         async move {
             Ok::<_, http::Error>(service_fn(move |req| {
                 handle_request(req, remote_addr, glob.clone())
@@ -58,8 +72,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    let shutdown_glob = glob.clone();
+    // Connections stop being accepted once the future resolves,
+    // the database has to stay open until the last request is handled
     Server::bind(&"127.0.0.1:5001".parse().unwrap())
         .serve(service)
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            shutdown::begin(&shutdown_glob).await;
+        })
         .await?;
+    shutdown::finish(&glob).await;
     Ok(())
 }
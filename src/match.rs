@@ -1,7 +1,12 @@
 #![allow(dead_code)]
-use crate::{packets::OsuEncode, Token};
+use crate::{
+    packets::{DecodeError, Id, OsuEncode, OsuPacket, Reader},
+    Token,
+};
 use std::{
     collections::HashMap,
+    convert::TryFrom,
+    ops::{Deref, DerefMut},
     sync::{
         atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
         Arc,
@@ -11,7 +16,7 @@ use tokio::sync::RwLock;
 
 enum_try_from!(
     #[repr(u8)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum SlotStatus {
         Free = 1,
         Locked = 2,
@@ -19,6 +24,7 @@ enum_try_from!(
         Ready = 8,
         NoMap = 16,
         Playing = 32,
+        Complete = 64,
         Occupied = 124,
         PlayingQuit = 128,
     }
@@ -28,9 +34,13 @@ impl SlotStatus {
     pub fn is_occupied(self) -> bool { self as u8 & SlotStatus::Occupied as u8 > 0 }
 }
 
+impl Default for SlotStatus {
+    fn default() -> Self { SlotStatus::Free }
+}
+
 enum_try_from!(
     #[repr(u8)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum Team {
         NoTeam = 0,
         Blue = 1,
@@ -38,9 +48,13 @@ enum_try_from!(
     }
 );
 
+impl Default for Team {
+    fn default() -> Self { Team::NoTeam }
+}
+
 enum_try_from!(
     #[repr(u8)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum ScoringType {
         Score,
         Accuracy,
@@ -51,7 +65,7 @@ enum_try_from!(
 
 enum_try_from!(
     #[repr(u8)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum TeamType {
         HeadToHead,
         TagCoop,
@@ -60,15 +74,100 @@ enum_try_from!(
     }
 );
 
+/// Ids of players in occupied slots, -1 for the rest,
+/// only the occupied ones are present in the data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlotIds(pub [i32; 16]);
+
+impl OsuEncode for SlotIds {
+    fn encoded_size(&self) -> usize { self.0.iter().filter(|&&id| id != -1).count() * 4 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for id in self.0.iter().filter(|&&id| id != -1) {
+            id.encode(buf);
+        }
+    }
+}
+
+fn get_slot_ids(
+    reader: &mut Reader,
+    slot_statuses: &[SlotStatus; 16],
+) -> Result<SlotIds, DecodeError> {
+    let mut slot_ids = [-1i32; 16];
+    for (i, s) in slot_statuses.iter().enumerate() {
+        if s.is_occupied() {
+            slot_ids[i] = reader.read()?;
+        }
+    }
+    Ok(SlotIds(slot_ids))
+}
+
+fn get_slot_mods(reader: &mut Reader, freemod: &bool) -> Result<Option<[u32; 16]>, DecodeError> {
+    if *freemod {
+        Ok(Some(reader.read()?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn parse_name<'a>(reader: &mut Reader<'a>) -> Result<&'a str, DecodeError> {
+    let name: &str = reader.read()?;
+    let name = name.trim();
+    if name.is_empty() {
+        Err(DecodeError::InvalidValue)
+    } else {
+        Ok(name)
+    }
+}
+
+/// Match as it's sent over the wire, both by the client when creating or
+/// changing the settings and by us with the id of whatever packet we're sending
+#[derive(OsuPacket, Debug, PartialEq)]
+pub struct MatchInfo<'a> {
+    pub id: u16,
+    pub in_progress: bool,
+    pub match_type: u8,
+    pub mods: u32,
+    #[decoder(parse_name)]
+    pub name: &'a str,
+    pub password: &'a str,
+    pub beatmap_name: &'a str,
+    pub beatmap_id: u32,
+    pub beatmap_md5: &'a str,
+    pub slot_statuses: [SlotStatus; 16],
+    pub slot_teams: [Team; 16],
+    #[decoder(get_slot_ids: slot_statuses)]
+    pub slot_ids: SlotIds,
+    pub host_id: i32,
+    pub game_mode: u8,
+    pub scoring_type: ScoringType,
+    pub team_type: TeamType,
+    pub freemod: bool,
+    #[decoder(get_slot_mods: freemod)]
+    pub slot_mods: Option<[u32; 16]>,
+    pub seed: i32,
+}
+
+impl MatchInfo<'_> {
+    pub fn to_packet(&self, id: Id) -> Vec<u8> { crate::packets::write_packet(id, self) }
+}
+
 #[derive(Debug)]
 pub struct Slot {
     pub status: AtomicU8, //SlotStatus,
     pub team: AtomicU8,   //Team,
     pub token: RwLock<Option<Arc<dyn Token>>>,
     pub skip: AtomicBool,
+    pub loaded: AtomicBool,
     pub mods: AtomicU32,
 }
 
+impl Slot {
+    fn has_status(&self, status: SlotStatus) -> bool {
+        self.status.load(Ordering::SeqCst) == status as u8
+    }
+}
+
 impl Default for Slot {
     fn default() -> Self {
         Slot {
@@ -76,11 +175,43 @@ impl Default for Slot {
             team: AtomicU8::new(Team::NoTeam as u8),
             token: RwLock::default(),
             skip: AtomicBool::default(),
+            loaded: AtomicBool::default(),
             mods: AtomicU32::new(0),
         }
     }
 }
 
+/// Matches by their id, together with the state needed to hand out new ids
+#[derive(Debug, Default)]
+pub struct MatchList {
+    matches: HashMap<u16, Arc<Match>>,
+    /// Where the search for a free id starts, so ids of closed matches
+    /// aren't handed out again right away
+    next_id: u16,
+}
+
+impl MatchList {
+    /// Finds the next id not used by any match, wrapping around at the end
+    fn allocate_id(&mut self) -> Option<u16> {
+        let start = self.next_id;
+        let id = (0..=u16::MAX)
+            .map(|i| start.wrapping_add(i))
+            .find(|id| !self.matches.contains_key(id))?;
+        self.next_id = id.wrapping_add(1);
+        Some(id)
+    }
+}
+
+impl Deref for MatchList {
+    type Target = HashMap<u16, Arc<Match>>;
+
+    fn deref(&self) -> &Self::Target { &self.matches }
+}
+
+impl DerefMut for MatchList {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.matches }
+}
+
 #[derive(Debug)]
 pub struct Match {
     pub id: u16,
@@ -97,98 +228,406 @@ pub struct Match {
     pub scoring_type: AtomicU8,
     pub team_type: AtomicU8,
     pub freemod: AtomicBool,
+    /// Ids of users who can control the match with `!mp`, separately from the host
+    pub referees: RwLock<Vec<i32>>,
+    /// Bumped whenever a pending start countdown should be cancelled
+    pub countdown: AtomicU32,
 }
 
 impl Match {
+    /// Returns `None` if every id is already taken
     pub async fn new(
-        list: &mut HashMap<u16, Arc<Match>>,
+        list: &mut MatchList,
         name: &str,
         password: &str,
         beatmap_name: &str,
         beatmap_id: u32,
         beatmap_md5: &str,
         owner: &Arc<dyn Token>,
-    ) -> Arc<Self> {
-        let slots = [
-            Slot {
-                status: AtomicU8::new(SlotStatus::NotReady as u8),
-                token: RwLock::new(Some(owner.clone())),
-                ..Slot::default()
-            },
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-            Slot::default(),
-        ];
-        let res = Match {
-            id: list.keys().count() as u16,
+    ) -> Option<Arc<Self>> {
+        let mut res = Match::build(
+            list.allocate_id()?,
+            name,
+            password,
+            beatmap_name,
+            beatmap_id,
+            beatmap_md5,
+            owner.id(),
+        );
+        res.slots[0] = Slot {
+            status: AtomicU8::new(SlotStatus::NotReady as u8),
+            token: RwLock::new(Some(owner.clone())),
+            ..Slot::default()
+        };
+        let res = Arc::new(res);
+        list.insert(res.id, res.clone());
+        Some(res)
+    }
+
+    /// Creates a match nobody is in, used by referees who don't want to play
+    pub fn new_empty(list: &mut MatchList, name: &str) -> Option<Arc<Self>> {
+        let res = Match::build(list.allocate_id()?, name, "", "", 0, "", -1);
+        let res = Arc::new(res);
+        list.insert(res.id, res.clone());
+        Some(res)
+    }
+
+    fn build(
+        id: u16,
+        name: &str,
+        password: &str,
+        beatmap_name: &str,
+        beatmap_id: u32,
+        beatmap_md5: &str,
+        host_id: i32,
+    ) -> Self {
+        Match {
+            id,
             name: RwLock::new(name.to_string()),
             password: RwLock::new(if password.is_empty() {
                 None
             } else {
                 Some(password.to_string())
             }),
-            slots,
+            slots: Default::default(),
             in_progress: AtomicBool::new(false),
             mods: AtomicU32::new(0),
             beatmap_id: AtomicU32::new(beatmap_id),
             beatmap_name: RwLock::new(beatmap_name.to_string()),
             beatmap_md5: RwLock::new(beatmap_md5.to_string()),
-            host_id: RwLock::new(owner.id()),
+            host_id: RwLock::new(host_id),
             game_mode: AtomicU8::new(0),
             scoring_type: AtomicU8::new(ScoringType::Score as u8),
             team_type: AtomicU8::new(TeamType::HeadToHead as u8),
             freemod: AtomicBool::new(false),
-        };
-        let res = Arc::new(res);
-        list.insert(res.id, res.clone());
+            referees: RwLock::default(),
+            countdown: AtomicU32::new(0),
+        }
+    }
+
+    pub fn slot_statuses(&self) -> [SlotStatus; 16] {
+        let mut res = [SlotStatus::Free; 16];
+        for (status, slot) in res.iter_mut().zip(self.slots.iter()) {
+            *status = SlotStatus::try_from(slot.status.load(Ordering::SeqCst)).unwrap_or_default();
+        }
         res
     }
 
-    pub fn slot_statuses(&self) -> Vec<u8> {
-        self.slots
-            .iter()
-            .map(|slot| slot.status.load(Ordering::SeqCst))
-            .collect()
+    pub fn slot_teams(&self) -> [Team; 16] {
+        let mut res = [Team::NoTeam; 16];
+        for (team, slot) in res.iter_mut().zip(self.slots.iter()) {
+            *team = Team::try_from(slot.team.load(Ordering::SeqCst)).unwrap_or_default();
+        }
+        res
+    }
+
+    pub async fn slot_ids(&self) -> SlotIds {
+        let mut res = [-1; 16];
+        for (id, slot) in res.iter_mut().zip(self.slots.iter()) {
+            if let Some(t) = slot.token.read().await.as_ref() {
+                *id = t.id();
+            }
+        }
+        SlotIds(res)
     }
 
-    pub fn slot_teams(&self) -> Vec<u8> {
+    pub fn slot_mods(&self) -> Option<[u32; 16]> {
+        if !self.freemod.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut res = [0; 16];
+        for (mods, slot) in res.iter_mut().zip(self.slots.iter()) {
+            *mods = slot.mods.load(Ordering::SeqCst);
+        }
+        Some(res)
+    }
+
+    pub fn channel_name(&self) -> String { format!("#multi_{}", self.id) }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(|slot| {
+            let status = slot.status.load(Ordering::SeqCst);
+            status & SlotStatus::Occupied as u8 == 0
+        })
+    }
+
+    /// Puts the token in the first free slot and returns that slot's index
+    pub async fn join(&self, token: &Arc<dyn Token>) -> Option<usize> {
+        for (i, slot) in self.slots.iter().enumerate() {
+            // Hold the token lock so nobody sees an occupied slot without a token
+            let mut t = slot.token.write().await;
+            let joined = slot
+                .status
+                .compare_exchange(
+                    SlotStatus::Free as u8,
+                    SlotStatus::NotReady as u8,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok();
+            if joined {
+                *t = Some(token.clone());
+                slot.team.store(Team::NoTeam as u8, Ordering::SeqCst);
+                slot.skip.store(false, Ordering::SeqCst);
+                slot.mods.store(0, Ordering::SeqCst);
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// Frees the slot occupied by the token and returns its index
+    pub async fn part(&self, token: &Arc<dyn Token>) -> Option<usize> {
+        for (i, slot) in self.slots.iter().enumerate() {
+            let mut t = slot.token.write().await;
+            match t.as_ref() {
+                Some(t) if Arc::ptr_eq(t, token) => (),
+                _ => continue,
+            }
+            *t = None;
+            slot.status.store(SlotStatus::Free as u8, Ordering::SeqCst);
+            slot.team.store(Team::NoTeam as u8, Ordering::SeqCst);
+            slot.skip.store(false, Ordering::SeqCst);
+            slot.mods.store(0, Ordering::SeqCst);
+            return Some(i);
+        }
+        None
+    }
+
+    /// Returns the index of the slot occupied by the user with the given id
+    pub async fn slot_of(&self, id: i32) -> Option<usize> {
+        for (i, slot) in self.slots.iter().enumerate() {
+            match slot.token.read().await.as_ref() {
+                Some(t) if t.id() == id => return Some(i),
+                _ => continue,
+            }
+        }
+        None
+    }
+
+    /// Toggles the slot between ready and not ready,
+    /// returns false if the slot was in neither of those states
+    pub fn set_ready(&self, slot: usize, ready: bool) -> bool {
+        let (from, to) = if ready {
+            (SlotStatus::NotReady, SlotStatus::Ready)
+        } else {
+            (SlotStatus::Ready, SlotStatus::NotReady)
+        };
+        let old = self.slots[slot]
+            .status
+            .compare_exchange(from as u8, to as u8, Ordering::SeqCst, Ordering::SeqCst)
+            .unwrap_or_else(|actual| actual);
+        old == from as u8 || old == to as u8
+    }
+
+    /// Moves every player who isn't missing the map into the playing state,
+    /// returns false if the match was already in progress
+    pub fn start(&self) -> bool {
+        if self
+            .in_progress
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return false;
+        }
+        for slot in self.slots.iter() {
+            if slot.has_status(SlotStatus::Ready) || slot.has_status(SlotStatus::NotReady) {
+                slot.skip.store(false, Ordering::SeqCst);
+                slot.loaded.store(false, Ordering::SeqCst);
+                slot.status.store(SlotStatus::Playing as u8, Ordering::SeqCst);
+            }
+        }
+        true
+    }
+
+    fn all_playing(&self, f: impl Fn(&Slot) -> bool) -> bool {
         self.slots
             .iter()
-            .map(|slot| slot.team.load(Ordering::SeqCst))
-            .collect()
+            .filter(|slot| slot.has_status(SlotStatus::Playing))
+            .all(f)
+    }
+
+    /// Marks the slot as loaded, returns true if everyone playing has loaded
+    pub fn load_complete(&self, slot: usize) -> bool {
+        self.slots[slot].loaded.store(true, Ordering::SeqCst);
+        self.all_playing(|slot| slot.loaded.load(Ordering::SeqCst))
+    }
+
+    /// Marks the slot as wanting to skip, returns true if everyone playing wants to
+    pub fn skip(&self, slot: usize) -> bool {
+        self.slots[slot].skip.store(true, Ordering::SeqCst);
+        self.all_playing(|slot| slot.skip.load(Ordering::SeqCst))
+    }
+
+    /// Marks the slot as done playing, returns true if this finished the match
+    pub fn complete(&self, slot: usize) -> bool {
+        self.slots[slot]
+            .status
+            .compare_exchange(
+                SlotStatus::Playing as u8,
+                SlotStatus::Complete as u8,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .ok();
+        self.try_finish()
     }
 
-    pub async fn slot_ids(&self) -> Vec<u8> {
-        let mut res = Vec::with_capacity(16 * 4);
+    /// Finishes the match if nobody is playing anymore, returns true if it did
+    pub fn try_finish(&self) -> bool {
+        if self.slots.iter().any(|slot| slot.has_status(SlotStatus::Playing)) {
+            return false;
+        }
+        self.finish()
+    }
+
+    /// Stops the match and moves every player back to not ready,
+    /// returns false if the match wasn't in progress
+    pub fn finish(&self) -> bool {
+        if self
+            .in_progress
+            .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return false;
+        }
         for slot in self.slots.iter() {
-            let token = slot.token.read().await.clone();
-            if let Some(t) = token {
-                t.id().encode(&mut res);
+            if slot.has_status(SlotStatus::Playing)
+                || slot.has_status(SlotStatus::Complete)
+                || slot.has_status(SlotStatus::PlayingQuit)
+            {
+                slot.status.store(SlotStatus::NotReady as u8, Ordering::SeqCst);
             }
+            slot.skip.store(false, Ordering::SeqCst);
+            slot.loaded.store(false, Ordering::SeqCst);
         }
-        res
+        true
     }
 
-    pub fn slot_mods(&self) -> Vec<u8> {
-        if !self.freemod.load(Ordering::SeqCst) {
-            return Vec::new();
+    pub async fn is_referee(&self, id: i32) -> bool { self.referees.read().await.contains(&id) }
+
+    /// Locks every free slot, or unlocks every locked one
+    pub fn set_locked(&self, locked: bool) {
+        let (from, to) = if locked {
+            (SlotStatus::Free, SlotStatus::Locked)
+        } else {
+            (SlotStatus::Locked, SlotStatus::Free)
+        };
+        for slot in self.slots.iter() {
+            slot.status
+                .compare_exchange(from as u8, to as u8, Ordering::SeqCst, Ordering::SeqCst)
+                .ok();
         }
-        let mut res = Vec::with_capacity(16 * 4);
-        self.slots
-            .iter()
-            .for_each(|slot| slot.mods.load(Ordering::SeqCst).encode(&mut res));
-        res
+    }
+
+    /// Unlocks the first `size` slots and locks the free ones after them
+    pub fn resize(&self, size: usize) {
+        for (i, slot) in self.slots.iter().enumerate() {
+            let (from, to) = if i < size {
+                (SlotStatus::Locked, SlotStatus::Free)
+            } else {
+                (SlotStatus::Free, SlotStatus::Locked)
+            };
+            slot.status
+                .compare_exchange(from as u8, to as u8, Ordering::SeqCst, Ordering::SeqCst)
+                .ok();
+        }
+    }
+
+    /// Moves the player to another slot, returns false if that slot isn't free
+    pub async fn move_player(&self, from: usize, to: usize) -> bool {
+        if from == to {
+            return true;
+        }
+        // Always lock in the same order so two moves can't deadlock
+        let (first, second) = (from.min(to), from.max(to));
+        let mut first = self.slots[first].token.write().await;
+        let mut second = self.slots[second].token.write().await;
+        let (from_token, to_token) = if from < to {
+            (&mut *first, &mut *second)
+        } else {
+            (&mut *second, &mut *first)
+        };
+        let (from, to) = (&self.slots[from], &self.slots[to]);
+        if from_token.is_none() || !to.has_status(SlotStatus::Free) {
+            return false;
+        }
+        *to_token = from_token.take();
+        to.status.store(
+            from.status.swap(SlotStatus::Free as u8, Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+        to.team.store(
+            from.team.swap(Team::NoTeam as u8, Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+        to.mods.store(from.mods.swap(0, Ordering::SeqCst), Ordering::SeqCst);
+        true
+    }
+
+    pub fn set_team(&self, slot: usize, team: Team) {
+        self.slots[slot].team.store(team as u8, Ordering::SeqCst);
+    }
+
+    pub fn is_team_mode(&self) -> bool {
+        let team_type = self.team_type.load(Ordering::SeqCst);
+        team_type == TeamType::TeamVs as u8 || team_type == TeamType::TagTeamVs as u8
+    }
+
+    /// Switches the slot to the other team and returns the new one
+    pub fn change_team(&self, slot: usize) -> Team {
+        let team = match Team::try_from(self.slots[slot].team.load(Ordering::SeqCst)) {
+            Ok(Team::Red) => Team::Blue,
+            _ => Team::Red,
+        };
+        self.set_team(slot, team);
+        team
+    }
+
+    /// Locks the slot if it's free or frees it if it's locked,
+    /// returns false if it was neither
+    pub fn toggle_lock(&self, slot: usize) -> bool {
+        let status = &self.slots[slot].status;
+        let swap = |from: SlotStatus, to: SlotStatus| {
+            status
+                .compare_exchange(from as u8, to as u8, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        };
+        swap(SlotStatus::Free, SlotStatus::Locked) || swap(SlotStatus::Locked, SlotStatus::Free)
+    }
+
+    /// Puts everyone whose beatmap or mods changed back to not ready
+    pub fn unready_all(&self) {
+        for slot in self.slots.iter() {
+            slot.status
+                .compare_exchange(
+                    SlotStatus::Ready as u8,
+                    SlotStatus::NotReady as u8,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .ok();
+        }
+    }
+
+    /// Returns the index of the slot occupied by the user with the given name
+    pub async fn slot_of_name(&self, username: &str) -> Option<usize> {
+        for (i, slot) in self.slots.iter().enumerate() {
+            match slot.token.read().await.as_ref() {
+                Some(t) if t.username() == username => return Some(i),
+                _ => continue,
+            }
+        }
+        None
+    }
+
+    /// Returns the token sitting in the first occupied slot
+    pub async fn first_player(&self) -> Option<Arc<dyn Token>> {
+        for slot in self.slots.iter() {
+            if let Some(t) = slot.token.read().await.as_ref() {
+                return Some(t.clone());
+            }
+        }
+        None
     }
 }
@@ -1,9 +1,69 @@
+use std::fmt;
 use tracing::error;
 
 pub trait OsuEncode {
     fn encoded_size(&self) -> usize;
     fn encode(&self, buf: &mut Vec<u8>);
-    fn decode<'a>(buf: &'a [u8]) -> Result<(&'a Self, usize), ()>;
+}
+
+/// Decoding produces owned values (or slices borrowed from the buffer for strings),
+/// so nothing ever gets reinterpreted in place
+pub trait OsuDecode<'a>: Sized {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError>;
+
+    /// Decodes a value from the start of the buffer, returning it and the amount of bytes read
+    fn decode(buf: &'a [u8]) -> Result<(Self, usize), DecodeError> {
+        let mut reader = Reader::new(buf);
+        let res = Self::read(&mut reader)?;
+        Ok((res, reader.position()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEof,
+    InvalidValue,
+    InvalidUtf8,
+    UnknownStringPrefix(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of data"),
+            DecodeError::InvalidValue => write!(f, "invalid value"),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid utf-8"),
+            DecodeError::UnknownStringPrefix(p) => write!(f, "unknown string prefix {:#x}", p),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Bounds-checked cursor over the data sent by the client
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self { Reader { buf, pos: 0 } }
+
+    pub fn position(&self) -> usize { self.pos }
+
+    pub fn remaining(&self) -> &'a [u8] { &self.buf[self.pos..] }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.remaining().len() < len {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let res = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(res)
+    }
+
+    pub fn read<T: OsuDecode<'a>>(&mut self) -> Result<T, DecodeError> { T::read(self) }
 }
 
 macro_rules! encode_impl {
@@ -11,18 +71,14 @@ macro_rules! encode_impl {
         impl OsuEncode for $t {
             fn encoded_size(&self) -> usize { std::mem::size_of::<$t>() }
 
-            fn encode(&self, buf: &mut Vec<u8>) {
-                let s = unsafe {
-                    std::slice::from_raw_parts(self as *const $t as *const u8, std::mem::size_of_val(self))
-                };
-                buf.extend_from_slice(s);
-            }
+            fn encode(&self, buf: &mut Vec<u8>) { buf.extend_from_slice(&self.to_le_bytes()); }
+        }
 
-            fn decode(buf: &[u8]) -> Result<(&Self, usize), ()> {
-                if buf.len() < std::mem::size_of::<$t>() { Err(()) }
-                else { Ok(unsafe {
-                    (&*(buf.as_ptr() as *const $t), std::mem::size_of::<$t>())
-                }) }
+        impl<'a> OsuDecode<'a> for $t {
+            fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+                let mut bytes = [0u8; std::mem::size_of::<$t>()];
+                bytes.copy_from_slice(reader.take(bytes.len())?);
+                Ok(<$t>::from_le_bytes(bytes))
             }
         }
     };
@@ -33,6 +89,8 @@ macro_rules! encode_impl {
 encode_impl!(u8, i16, u16, u32, i32, u64, f32);
 
 mod leb128 {
+    use super::{DecodeError, Reader};
+
     #[inline]
     pub fn encode(out: &mut Vec<u8>, mut value: u32) {
         loop {
@@ -47,18 +105,17 @@ mod leb128 {
     }
 
     #[inline]
-    pub fn decode(slice: &[u8]) -> (u32, usize) {
+    pub fn decode(reader: &mut Reader) -> Result<u32, DecodeError> {
         let mut result = 0;
         let mut shift = 0;
-        let mut position = 0;
         loop {
-            let byte = slice[position];
-            position += 1;
+            let byte: u8 = reader.read()?;
+            if shift > 28 {
+                return Err(DecodeError::InvalidValue);
+            }
+            result |= ((byte & 0x7F) as u32) << shift;
             if (byte & 0x80) == 0 {
-                result |= (byte as u32) << shift;
-                return (result, position);
-            } else {
-                result |= ((byte & 0x7F) as u32) << shift;
+                return Ok(result);
             }
             shift += 7;
         }
@@ -77,30 +134,32 @@ impl OsuEncode for str {
             buf.extend_from_slice(self.as_bytes());
         }
     }
+}
 
-    fn decode(buf: &[u8]) -> Result<(&str, usize), ()> {
-        let prefix = unsafe { *buf.as_ptr() };
+impl<'a> OsuDecode<'a> for &'a str {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let prefix: u8 = reader.read()?;
         match prefix {
-            0 => Ok(("", 1)),
+            0 => Ok(""),
             0xb => {
-                let buf = &buf[1..];
-                let (len, off) = leb128::decode(buf);
-                let len = len as usize;
-                if buf.len() < off + len {
-                    return Err(());
-                }
-                let buf = &buf[off..off + len];
-                let res = unsafe { std::str::from_utf8_unchecked(buf) };
-                Ok((res, len + off + 1))
+                let len = leb128::decode(reader)? as usize;
+                let buf = reader.take(len)?;
+                std::str::from_utf8(buf).map_err(|_| DecodeError::InvalidUtf8)
             }
             _ => {
-                error!(?buf, "unknown string prefix");
-                Err(())
+                error!(?prefix, "unknown string prefix");
+                Err(DecodeError::UnknownStringPrefix(prefix))
             }
         }
     }
 }
 
+impl<T: OsuEncode + ?Sized> OsuEncode for &T {
+    fn encoded_size(&self) -> usize { (**self).encoded_size() }
+
+    fn encode(&self, buf: &mut Vec<u8>) { (**self).encode(buf) }
+}
+
 impl OsuEncode for bool {
     fn encoded_size(&self) -> usize { 1 }
 
@@ -111,66 +170,89 @@ impl OsuEncode for bool {
             buf.push(0);
         }
     }
+}
 
-    fn decode<'a>(buf: &'a [u8]) -> Result<(&'a Self, usize), ()> {
-        let (&val, off) = u8::decode(buf)?;
-        Ok((if val == 0 { &false } else { &true }, off))
+impl<'a> OsuDecode<'a> for bool {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let val: u8 = reader.read()?;
+        Ok(val != 0)
     }
 }
 
 impl OsuEncode for [i32] {
     fn encoded_size(&self) -> usize { self.len() * 4 + 2 }
 
-    // EXTREMELY unsafe, make sure the buffer has enough capacity to hold the data
     fn encode(&self, buf: &mut Vec<u8>) {
-        //debug_assert!(buf.capacity() - buf.len() >= self.encoded_size());
         (self.len() as u16).encode(buf);
-        // unsafe {
-        //     let src = self.as_ptr() as *const u8;
-        //     let dst = buf.as_mut_ptr().add(buf.len());
-        //     std::ptr::copy_nonoverlapping(src, dst, self.len() * 4);
-        //     buf.set_len(buf.len() + self.len() * 4);
-        // }
-        let src = unsafe { std::slice::from_raw_parts(self.as_ptr() as *const u8, self.len() * 4) };
-        buf.extend_from_slice(src);
+        for val in self {
+            val.encode(buf);
+        }
     }
+}
 
-    fn decode(buf: &[u8]) -> Result<(&[i32], usize), ()> {
-        let (&len, off) = u16::decode(buf)?;
-        let len = len as usize;
-        if buf.len() < off + len * 4 {
-            return Err(());
+/// Lists are prefixed with their length as an u16
+impl<'a, T: OsuDecode<'a>> OsuDecode<'a> for Vec<T> {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let len: u16 = reader.read()?;
+        let mut res = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            res.push(reader.read()?);
         }
-        #[allow(clippy::cast_ptr_alignment)]
-        let data = unsafe { std::slice::from_raw_parts(buf.as_ptr().add(off) as *const i32, len) };
-        let off = off + len * 4;
-        Ok((data, off))
+        Ok(res)
     }
 }
 
+impl<T> OsuEncode for Vec<T>
+where
+    [T]: OsuEncode,
+{
+    fn encoded_size(&self) -> usize { self[..].encoded_size() }
+
+    fn encode(&self, buf: &mut Vec<u8>) { self[..].encode(buf) }
+}
+
 impl OsuEncode for [u8] {
     fn encoded_size(&self) -> usize { self.len() }
 
     fn encode(&self, buf: &mut Vec<u8>) { buf.extend_from_slice(self); }
+}
 
-    fn decode(_buf: &[u8]) -> Result<(&Self, usize), ()> { unimplemented!() }
+/// Raw bytes aren't prefixed with anything, so they take up the rest of the data
+impl<'a> OsuDecode<'a> for &'a [u8] {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let len = reader.remaining().len();
+        reader.take(len)
+    }
+}
+
+/// Optional values are only present in the data when some earlier field says so,
+/// so they need a custom decoder
+impl<T: OsuEncode> OsuEncode for Option<T> {
+    fn encoded_size(&self) -> usize { self.as_ref().map_or(0, OsuEncode::encoded_size) }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(val) = self {
+            val.encode(buf);
+        }
+    }
 }
 
 impl<T: OsuEncode, const N: usize> OsuEncode for [T; N] {
-    fn encoded_size(&self) -> usize { self.len() * std::mem::size_of::<T>() }
+    fn encoded_size(&self) -> usize { self.iter().map(OsuEncode::encoded_size).sum() }
 
     fn encode(&self, buf: &mut Vec<u8>) {
-        let data = unsafe {
-            std::slice::from_raw_parts(self.as_ptr() as *const u8, std::mem::size_of_val(self))
-        };
-        buf.extend_from_slice(data);
+        for val in self.iter() {
+            val.encode(buf);
+        }
     }
+}
 
-    fn decode(buf: &[u8]) -> Result<(&Self, usize), ()> {
-        if buf.len() < N {
-            return Err(());
+impl<'a, T: OsuDecode<'a> + Copy + Default, const N: usize> OsuDecode<'a> for [T; N] {
+    fn read(reader: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let mut res = [T::default(); N];
+        for val in res.iter_mut() {
+            *val = reader.read()?;
         }
-        let ret = unsafe { &*(buf.as_ptr() as *const [T; N]) };
-        Ok((ret, std::mem::size_of_val(ret)))
+        Ok(res)
     }
 }
@@ -1,9 +1,49 @@
 use std::convert::TryFrom;
 pub mod encoding;
 pub mod server;
-pub use encoding::OsuEncode;
+pub use encoding::{DecodeError, OsuDecode, OsuEncode, Reader};
+pub use isoku_macros::OsuPacket;
 pub use uncho_common::packets::Id;
 
+/// Bytes in front of every packet's data: the id, a padding byte and the data length
+pub const HEADER_LEN: usize = 7;
+
+/// Structs which are sent as a packet with a fixed id, implemented by `#[derive(OsuPacket)]`
+pub trait OsuPacket: OsuEncode {
+    const ID: Id;
+
+    fn to_packet(&self) -> Vec<u8> { write_packet(Self::ID, self) }
+}
+
+/// Writes the packet header followed by the data
+pub fn write_packet<T: OsuEncode + ?Sized>(id: Id, data: &T) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + data.encoded_size());
+    id.encode(&mut buf);
+    buf.push(0);
+    buf.extend_from_slice(&[0; 4]);
+    data.encode(&mut buf);
+    // Sizes of strings are only estimated, so the length is filled in afterwards
+    let len = (buf.len() - HEADER_LEN) as u32;
+    buf[3..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+    buf
+}
+
+/// Reads a whole packet, checking that its id matches
+pub fn read_packet<'a, T: OsuPacket + OsuDecode<'a>>(buf: &'a [u8]) -> Result<T, DecodeError> {
+    let (id, data) = packet_data(buf)?;
+    if id as u16 != T::ID as u16 {
+        return Err(DecodeError::InvalidValue);
+    }
+    let (res, _) = T::decode(data)?;
+    Ok(res)
+}
+
+/// Splits the first packet into its id and data, without the header
+pub fn packet_data(buf: &[u8]) -> Result<(Id, &[u8]), DecodeError> {
+    let (id, len) = parse_packet(buf)?;
+    Ok((id, &buf[HEADER_LEN..HEADER_LEN + len]))
+}
+
 #[macro_use]
 macro_rules! count_items {
     () => { 0 };
@@ -14,16 +54,18 @@ macro_rules! count_items {
 macro_rules! impl_osu_encode {
     ($name:ident : $t:ident) => {
         impl $crate::packets::OsuEncode for $name {
-            fn encoded_size(&self) -> usize { std::mem::size_of_val(self) }
+            fn encoded_size(&self) -> usize { std::mem::size_of::<$t>() }
 
             fn encode(&self, buf: &mut Vec<u8>) { (*self as $t).encode(buf); }
+        }
 
-            fn decode(buf: &[u8]) -> Result<(&Self, usize), ()> {
-                //use $crate::packets::OsuEncode;
-                let (&val, off) = <$t>::decode(buf)?;
+        impl<'a> $crate::packets::OsuDecode<'a> for $name {
+            fn read(
+                reader: &mut $crate::packets::Reader<'a>,
+            ) -> Result<Self, $crate::packets::DecodeError> {
+                let val: $t = reader.read()?;
                 use std::convert::TryFrom;
-                Self::try_from(val)?;
-                Ok((unsafe { &*(buf.as_ptr() as *const Self) }, off))
+                Self::try_from(val).map_err(|_| $crate::packets::DecodeError::InvalidValue)
             }
         }
     };
@@ -64,16 +106,15 @@ macro_rules! enum_try_from {
             fn encode(&self, buf: &mut Vec<u8>) {
                 (*self as $t).encode(buf);
             }
+        }
 
-            fn decode(buf: &[u8]) -> Result<(&Self, usize), ()> {
-                //use $crate::packets::OsuEncode;
-                let (&val, off) = <$t>::decode(buf)?;
+        impl<'a> $crate::packets::OsuDecode<'a> for $name {
+            fn read(
+                reader: &mut $crate::packets::Reader<'a>,
+            ) -> Result<Self, $crate::packets::DecodeError> {
+                let val: $t = reader.read()?;
                 use std::convert::TryFrom;
-                Self::try_from(val)?;
-                Ok((
-                    unsafe { &*(buf.as_ptr() as *const Self) },
-                    off
-                ))
+                Self::try_from(val).map_err(|_| $crate::packets::DecodeError::InvalidValue)
             }
         }
     };
@@ -110,28 +151,28 @@ macro_rules! enum_try_from {
             fn encode(&self, buf: &mut Vec<u8>) {
                 (*self as $t).encode(buf);
             }
+        }
 
-            fn decode(buf: &[u8]) -> Result<(&Self, usize), ()> {
-                //use $crate::packets::OsuEncode;
-                let (&val, off) = <$t>::decode(buf)?;
+        impl<'a> $crate::packets::OsuDecode<'a> for $name {
+            fn read(
+                reader: &mut $crate::packets::Reader<'a>,
+            ) -> Result<Self, $crate::packets::DecodeError> {
+                let val: $t = reader.read()?;
                 use std::convert::TryFrom;
-                Self::try_from(val)?;
-                Ok((
-                    unsafe { &*(buf.as_ptr() as *const Self) },
-                    off
-                ))
+                Self::try_from(val).map_err(|_| $crate::packets::DecodeError::InvalidValue)
             }
         }
     }
 }
 
-pub fn parse_packet(buf: &[u8]) -> Result<(Id, usize), ()> {
-    let (&id, _) = u16::decode(buf)?;
-    let buf = &buf[3..];
-    let (&len, _) = u32::decode(buf)?;
-    if (&buf[4..]).len() < len as usize {
-        println!("{:?} {} {}", id, &buf[4..].len(), len);
-        return Err(());
+pub fn parse_packet(buf: &[u8]) -> Result<(Id, usize), DecodeError> {
+    let mut reader = Reader::new(buf);
+    let id: u16 = reader.read()?;
+    reader.take(1)?;
+    let len: u32 = reader.read()?;
+    if reader.remaining().len() < len as usize {
+        tracing::debug!(id, len, remaining = reader.remaining().len(), "packet cut short");
+        return Err(DecodeError::UnexpectedEof);
     }
     Ok((Id::try_from(id).unwrap_or(Id::Unknown), len as usize))
 }
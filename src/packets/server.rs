@@ -1,173 +1,518 @@
 #![allow(dead_code)]
-use super::{Id, OsuEncode};
-use crate::{token::Token, Channel, Match};
-use std::sync::atomic::Ordering;
-
-macro_rules! guess_size {
-    ($val:expr) => { $val.encoded_size() };
-    ($a:expr, $($b:expr),+) => { $a.encoded_size() + guess_size!($($b),+) };
-}
-
-macro_rules! build_packet {
-    ($id:expr => $($val:expr),+) => { {
-        let mut data = Vec::with_capacity(guess_size!($($val),+));
-        $($val.encode(&mut data);)+
-        let size = data.len() + 7;
-        let mut buf = Vec::with_capacity(size);
-        $id.encode(&mut buf);
-        buf.push(0);
-        (data.len() as i32).encode(&mut buf);
-        buf.append(&mut data);
-        buf
-    } };
-
-    ($id:expr) => {{
-        let mut buf = Vec::with_capacity(7);
-        $id.encode(&mut buf);
-        buf.push(0);
-        (0u32).encode(&mut buf);
-        buf
-    }}
-}
+use super::{Id, OsuPacket};
+use crate::{
+    r#match::{MatchInfo, ScoringType, TeamType},
+    token::Token,
+    Channel, Match,
+};
+use std::{convert::TryFrom, sync::atomic::Ordering};
 
 // ---INFO---
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::SilenceEnd)]
+pub struct SilenceEnd {
+    pub seconds: u32,
+}
+
 #[inline]
-pub fn silence_end(sec: u32) -> Vec<u8> { build_packet!(Id::SilenceEnd => sec) }
+pub fn silence_end(seconds: u32) -> Vec<u8> { SilenceEnd { seconds }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ProtocolVersion)]
+pub struct ProtocolVersion {
+    pub version: u32,
+}
 
 #[inline]
-pub fn protocol_ver(version: u32) -> Vec<u8> { build_packet!(Id::ProtocolVersion => version) }
+pub fn protocol_ver(version: u32) -> Vec<u8> { ProtocolVersion { version }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::UserPresenceBundle)]
+pub struct UserPresenceBundle {
+    pub users: Vec<i32>,
+}
 
 #[inline]
 pub fn online_users(user_list: &[i32]) -> Vec<u8> {
-    build_packet!(Id::UserPresenceBundle => user_list)
+    UserPresenceBundle {
+        users: user_list.to_vec(),
+    }
+    .to_packet()
 }
 
 // ---LOGIN---
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::UserId)]
+pub struct UserId {
+    pub id: i32,
+}
+
 #[inline]
-pub fn login_failed() -> Vec<u8> { build_packet!(Id::UserId => -1_i32) }
+pub fn login_failed() -> Vec<u8> { UserId { id: -1 }.to_packet() }
 
 #[inline]
-pub fn user_id(id: i32) -> Vec<u8> { build_packet!(Id::UserId => id) }
+pub fn user_id(id: i32) -> Vec<u8> { UserId { id }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::SupporterGmt)]
+pub struct SupporterGmt {
+    pub privileges: u32,
+}
 
 #[inline]
-pub fn user_rank(_rank: u32) -> Vec<u8> { build_packet!(Id::SupporterGmt => 38u32) }
+pub fn user_rank(_rank: u32) -> Vec<u8> { SupporterGmt { privileges: 38 }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::UserLogout)]
+pub struct UserLogout {
+    pub id: i32,
+    pub reason: u8,
+}
 
 #[inline]
-pub fn logout(token: &dyn Token) -> Vec<u8> { build_packet!(Id::UserLogout => token.id(), 0u8) }
+pub fn logout(token: &dyn Token) -> Vec<u8> {
+    UserLogout {
+        id: token.id(),
+        reason: 0,
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::UserSilenced)]
+pub struct UserSilenced {
+    pub id: i32,
+}
+
+#[inline]
+pub fn user_silenced(id: i32) -> Vec<u8> { UserSilenced { id }.to_packet() }
 
 // ---USER INFO---
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::UserPanel)]
+pub struct UserPanel<'a> {
+    pub id: i32,
+    pub username: &'a str,
+    pub utc_offset: u8,
+    pub country: u8,
+    pub privileges: u8,
+    pub longitude: f32,
+    pub latitude: f32,
+    pub rank: u32,
+}
+
 #[inline]
 pub fn user_panel(token: &dyn Token) -> Vec<u8> {
-    build_packet!(Id::UserPanel =>
-        token.id(), token.username(), 0i16, 16u8, 0f32, 0f32, 1u32)
+    UserPanel {
+        id: token.id(),
+        username: token.username(),
+        utc_offset: 0,
+        country: 0,
+        privileges: 16,
+        longitude: 0.0,
+        latitude: 0.0,
+        rank: 1,
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::UserStats)]
+pub struct UserStats<'a> {
+    pub id: i32,
+    pub action: u8,
+    pub action_text: &'a str,
+    pub action_md5: &'a str,
+    pub action_mods: u32,
+    pub game_mode: u8,
+    pub beatmap_id: u32,
+    pub ranked_score: u64,
+    pub accuracy: f32,
+    pub playcount: u32,
+    pub total_score: u64,
+    pub rank: u32,
+    pub pp: u16,
 }
 
 #[inline]
 pub async fn user_stats(token: &dyn Token) -> Vec<u8> {
     let stats = token.stats().await;
-    let action_id = stats.action as u8;
-    let action_text = &stats.action_text;
-    let action_md5 = &stats.action_md5;
-    let game_mode = stats.game_mode as u8;
-    build_packet!(Id::UserStats =>
-        token.id(),
-        action_id,
-        action_text,
-        action_md5,
-        stats.action_mods,
-        game_mode,
-        stats.beatmap_id,
-        stats.ranked_score,
-        stats.accuracy,
-        stats.playcount,
-        stats.total_score,
-        stats.rank,
-        stats.pp
-    )
-}
-
-#[inline]
-pub fn friend_list(users: &[i32]) -> Vec<u8> { build_packet!(Id::FriendsList => users) }
+    UserStats {
+        id: token.id(),
+        action: stats.action as u8,
+        action_text: &stats.action_text,
+        action_md5: &stats.action_md5,
+        action_mods: stats.action_mods,
+        game_mode: stats.game_mode as u8,
+        beatmap_id: stats.beatmap_id,
+        ranked_score: stats.ranked_score,
+        accuracy: stats.accuracy,
+        playcount: stats.playcount,
+        total_score: stats.total_score,
+        rank: stats.rank,
+        pp: stats.pp,
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::FriendsList)]
+pub struct FriendsList {
+    pub users: Vec<i32>,
+}
+
+#[inline]
+pub fn friend_list(users: &[i32]) -> Vec<u8> {
+    FriendsList {
+        users: users.to_vec(),
+    }
+    .to_packet()
+}
 
 // ---CHAT---
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ChannelInfoEnd)]
+pub struct ChannelInfoEnd {
+    pub unused: u32,
+}
+
 #[inline]
-pub fn channel_info_end() -> Vec<u8> { build_packet!(Id::ChannelInfoEnd => 0u32) }
+pub fn channel_info_end() -> Vec<u8> { ChannelInfoEnd { unused: 0 }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ChannelInfo)]
+pub struct ChannelInfo<'a> {
+    pub name: &'a str,
+    pub desc: &'a str,
+    pub users: u16,
+}
 
 #[inline]
 pub async fn channel_info(channel: &Channel) -> Vec<u8> {
-    let name = channel.name();
-    let desc = &channel.desc;
-    let users = channel.users.read().await.len() as u16;
-    build_packet!(Id::ChannelInfo => name, desc, users)
+    ChannelInfo {
+        name: channel.name(),
+        desc: &channel.desc,
+        users: channel.users.read().await.len() as u16,
+    }
+    .to_packet()
+}
+
+/// Same as `ChannelInfo`, but the client opens a tab for the channel right away
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ChannelAutoJoin)]
+pub struct ChannelAutoJoin<'a> {
+    pub name: &'a str,
+    pub desc: &'a str,
+    pub users: u16,
+}
+
+#[inline]
+pub async fn channel_auto_join(channel: &Channel) -> Vec<u8> {
+    ChannelAutoJoin {
+        name: channel.name(),
+        desc: &channel.desc,
+        users: channel.users.read().await.len() as u16,
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ChannelJoinSuccess)]
+pub struct ChannelJoinSuccess<'a> {
+    pub name: &'a str,
 }
 
 #[inline]
 pub fn channel_join_success(channel: &Channel) -> Vec<u8> {
-    build_packet!(Id::ChannelJoinSuccess => channel.name())
+    ChannelJoinSuccess {
+        name: channel.name(),
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ChannelKicked)]
+pub struct ChannelKicked<'a> {
+    pub name: &'a str,
 }
 
 #[inline]
 pub fn channel_kicked(channel: &Channel) -> Vec<u8> {
-    build_packet!(Id::ChannelKicked => channel.name())
+    ChannelKicked {
+        name: channel.name(),
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::SendMessage)]
+pub struct SendMessage<'a> {
+    pub from: &'a str,
+    pub content: &'a str,
+    pub to: &'a str,
+    pub from_id: i32,
 }
 
 #[inline]
 pub fn send_message(from: &dyn Token, to: &str, content: &str) -> Vec<u8> {
-    build_packet!(Id::SendMessage => from.username(), content, to, from.id())
+    message_from(from.username(), from.id(), to, content)
+}
+
+/// Message from someone who might not be online anymore
+#[inline]
+pub fn message_from(from: &str, from_id: i32, to: &str, content: &str) -> Vec<u8> {
+    SendMessage {
+        from,
+        content,
+        to,
+        from_id,
+    }
+    .to_packet()
+}
+
+// ---SPECTATOR---
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::SpectatorJoined)]
+pub struct SpectatorJoined {
+    pub id: i32,
+}
+
+#[inline]
+pub fn spectator_joined(id: i32) -> Vec<u8> { SpectatorJoined { id }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::SpectatorLeft)]
+pub struct SpectatorLeft {
+    pub id: i32,
+}
+
+#[inline]
+pub fn spectator_left(id: i32) -> Vec<u8> { SpectatorLeft { id }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::FellowSpectatorJoined)]
+pub struct FellowSpectatorJoined {
+    pub id: i32,
+}
+
+#[inline]
+pub fn fellow_spectator_joined(id: i32) -> Vec<u8> { FellowSpectatorJoined { id }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::FellowSpectatorLeft)]
+pub struct FellowSpectatorLeft {
+    pub id: i32,
+}
+
+#[inline]
+pub fn fellow_spectator_left(id: i32) -> Vec<u8> { FellowSpectatorLeft { id }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ServerSpectateFrames)]
+pub struct SpectateFrames<'a> {
+    pub frames: &'a [u8],
+}
+
+#[inline]
+pub fn spectate_frames(frames: &[u8]) -> Vec<u8> { SpectateFrames { frames }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::SpectatorCantSpectate)]
+pub struct SpectatorCantSpectate {
+    pub id: i32,
 }
 
+#[inline]
+pub fn spectator_cant_spectate(id: i32) -> Vec<u8> { SpectatorCantSpectate { id }.to_packet() }
+
 // ---MULTI---
+/// Sent to the lobby instead of the real password, the client only cares if there is one
+const HIDDEN_PASSWORD: &str = "*";
+
 #[inline]
-async fn match_info(id: Id, m: &Match) -> Vec<u8> {
-    let p = m.password.read().await;
-    let password = if let Some(pass) = &*p { pass } else { "" };
-    let match_name = &m.name.read().await;
+async fn match_info(id: Id, m: &Match, show_password: bool) -> Vec<u8> {
+    let password = m.password.read().await;
+    let password = match password.as_deref() {
+        Some(_) if !show_password => HIDDEN_PASSWORD,
+        Some(password) => password,
+        None => "",
+    };
+    let name = m.name.read().await;
     let beatmap_name = m.beatmap_name.read().await;
-    let beatmap_id = m.beatmap_id.load(Ordering::SeqCst);
     let beatmap_md5 = m.beatmap_md5.read().await;
-    let slot_ids = m.slot_ids().await;
-    let host_id = m.host_id.read().await;
-    build_packet!(id =>
-        m.id,
-        m.in_progress.load(Ordering::SeqCst) as u8,
-        0u8,
-        m.mods.load(Ordering::SeqCst),
-        match_name,
+    MatchInfo {
+        id: m.id,
+        in_progress: m.in_progress.load(Ordering::SeqCst),
+        match_type: 0,
+        mods: m.mods.load(Ordering::SeqCst),
+        name: &name,
         password,
-        beatmap_name,
-        beatmap_id,
-        beatmap_md5,
-        m.slot_statuses(),
-        m.slot_teams(),
-        slot_ids,
-        host_id,
-        m.game_mode.load(Ordering::SeqCst),
-        m.scoring_type.load(Ordering::SeqCst),
-        m.team_type.load(Ordering::SeqCst),
-        m.freemod.load(Ordering::SeqCst) as u8,
-        m.slot_mods(),
-        0u32
-    )
+        beatmap_name: &beatmap_name,
+        beatmap_id: m.beatmap_id.load(Ordering::SeqCst),
+        beatmap_md5: &beatmap_md5,
+        slot_statuses: m.slot_statuses(),
+        slot_teams: m.slot_teams(),
+        slot_ids: m.slot_ids().await,
+        host_id: *m.host_id.read().await,
+        game_mode: m.game_mode.load(Ordering::SeqCst),
+        scoring_type: ScoringType::try_from(m.scoring_type.load(Ordering::SeqCst))
+            .unwrap_or(ScoringType::Score),
+        team_type: TeamType::try_from(m.team_type.load(Ordering::SeqCst))
+            .unwrap_or(TeamType::HeadToHead),
+        freemod: m.freemod.load(Ordering::SeqCst),
+        slot_mods: m.slot_mods(),
+        seed: 0,
+    }
+    .to_packet(id)
 }
 
 #[inline]
-pub async fn create_match(m: &Match) -> Vec<u8> { match_info(Id::CreateMatch, m).await }
+pub async fn create_match(m: &Match) -> Vec<u8> { match_info(Id::CreateMatch, m, false).await }
+
+#[inline]
+pub async fn match_join_success(m: &Match) -> Vec<u8> {
+    match_info(Id::MatchJoinSuccess, m, true).await
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchJoinFail)]
+pub struct MatchJoinFail;
 
 #[inline]
-pub async fn match_join_success(m: &Match) -> Vec<u8> { match_info(Id::MatchJoinSuccess, m).await }
+pub fn match_join_fail() -> Vec<u8> { MatchJoinFail.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ServerMatchTransferHost)]
+pub struct MatchTransferHost;
 
 #[inline]
-pub fn match_join_fail() -> Vec<u8> { build_packet!(Id::MatchJoinFail) }
+pub fn match_transfer_host() -> Vec<u8> { MatchTransferHost.to_packet() }
 
 #[inline]
-pub fn match_transfer_host() -> Vec<u8> { build_packet!(Id::ServerMatchTransferHost) }
+pub async fn update_match(m: &Match) -> Vec<u8> { match_info(Id::UpdateMatch, m, true).await }
 
+/// Same as `update_match` but without the password, for people who aren't in the match
 #[inline]
-pub async fn update_match(m: &Match) -> Vec<u8> { match_info(Id::UpdateMatch, m).await }
+pub async fn lobby_update_match(m: &Match) -> Vec<u8> {
+    match_info(Id::UpdateMatch, m, false).await
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchInvite)]
+pub struct MatchInvite<'a> {
+    pub from: &'a str,
+    pub content: &'a str,
+    pub to: &'a str,
+    pub from_id: i32,
+}
+
+#[inline]
+pub fn match_invite(from: &dyn Token, to: &str, content: &str) -> Vec<u8> {
+    MatchInvite {
+        from: from.username(),
+        content,
+        to,
+        from_id: from.id(),
+    }
+    .to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchChangePassword)]
+pub struct MatchChangePassword<'a> {
+    pub password: &'a str,
+}
+
+#[inline]
+pub fn match_change_password(password: &str) -> Vec<u8> {
+    MatchChangePassword { password }.to_packet()
+}
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::DisposeMatch)]
+pub struct DisposeMatch {
+    pub id: i32,
+}
+
+#[inline]
+pub fn dispose_match(id: u16) -> Vec<u8> { DisposeMatch { id: id as i32 }.to_packet() }
+
+#[inline]
+pub async fn match_start(m: &Match) -> Vec<u8> { match_info(Id::ServerMatchStart, m, true).await }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchAllPlayersLoaded)]
+pub struct MatchAllPlayersLoaded;
+
+#[inline]
+pub fn match_all_players_loaded() -> Vec<u8> { MatchAllPlayersLoaded.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchPlayerSkipped)]
+pub struct MatchPlayerSkipped {
+    pub slot: i32,
+}
+
+#[inline]
+pub fn match_player_skipped(slot: i32) -> Vec<u8> { MatchPlayerSkipped { slot }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchSkip)]
+pub struct MatchSkip;
+
+#[inline]
+pub fn match_skip() -> Vec<u8> { MatchSkip.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ServerMatchScoreUpdate)]
+pub struct MatchScoreUpdate<'a> {
+    pub frame: &'a [u8],
+}
+
+#[inline]
+pub fn match_score_update(frame: &[u8]) -> Vec<u8> { MatchScoreUpdate { frame }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::MatchPlayerFailed)]
+pub struct MatchPlayerFailed {
+    pub slot: i32,
+}
+
+#[inline]
+pub fn match_player_failed(slot: i32) -> Vec<u8> { MatchPlayerFailed { slot }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ServerMatchComplete)]
+pub struct MatchComplete;
+
+#[inline]
+pub fn match_complete() -> Vec<u8> { MatchComplete.to_packet() }
 
 // ---UTILS---
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::Notification)]
+pub struct Notification<'a> {
+    pub text: &'a str,
+}
+
 #[inline]
-pub fn notification(text: &str) -> Vec<u8> { build_packet!(Id::Notification => text) }
+pub fn notification(text: &str) -> Vec<u8> { Notification { text }.to_packet() }
+
+/// Makes the client reconnect after `delay` milliseconds
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::ServerRestart)]
+pub struct ServerRestart {
+    pub delay: i32,
+}
+
+#[inline]
+pub fn server_restart(delay: i32) -> Vec<u8> { ServerRestart { delay }.to_packet() }
+
+#[derive(OsuPacket, Debug, PartialEq)]
+#[packet(Id::Jumpscare)]
+pub struct Jumpscare<'a> {
+    pub text: &'a str,
+}
 
 #[inline]
-pub fn jumpscare(text: &str) -> Vec<u8> { build_packet!(Id::Jumpscare => text) }
+pub fn jumpscare(text: &str) -> Vec<u8> { Jumpscare { text }.to_packet() }
@@ -0,0 +1,51 @@
+use crate::Glob;
+use std::{ops::BitOr, str::FromStr};
+
+/// Set of flags saying what the user is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Privileges(pub u32);
+
+impl Privileges {
+    pub const NORMAL: Privileges = Privileges(1);
+    pub const SUPPORTER: Privileges = Privileges(1 << 1);
+    pub const MODERATOR: Privileges = Privileges(1 << 2);
+    pub const ADMIN: Privileges = Privileges(1 << 3);
+
+    pub const fn contains(self, other: Privileges) -> bool { self.0 & other.0 == other.0 }
+
+    /// Admins can do everything moderators can
+    pub const fn has(self, other: Privileges) -> bool {
+        self.contains(other) || self.contains(Privileges::ADMIN)
+    }
+}
+
+impl BitOr for Privileges {
+    type Output = Privileges;
+
+    fn bitor(self, rhs: Privileges) -> Privileges { Privileges(self.0 | rhs.0) }
+}
+
+/// Either the name of a single privilege or the raw number
+impl FromStr for Privileges {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Privileges, ()> {
+        match s.to_lowercase().as_str() {
+            "normal" => Ok(Privileges::NORMAL),
+            "supporter" => Ok(Privileges::SUPPORTER),
+            "moderator" | "mod" => Ok(Privileges::MODERATOR),
+            "admin" => Ok(Privileges::ADMIN),
+            s => s.parse().map(Privileges).map_err(|_| ()),
+        }
+    }
+}
+
+/// Loads the user's privileges from the database, users without a row are normal users
+pub async fn load(id: i32, glob: &Glob) -> Result<Privileges, sqlx::Error> {
+    let row: Option<(i32,)> =
+        sqlx::query_as("SELECT privileges FROM user_privileges WHERE user_id = $1")
+            .bind(id)
+            .fetch_optional(&glob.db_pool)
+            .await?;
+    Ok(row.map_or(Privileges::NORMAL, |(p,)| Privileges(p as u32)))
+}
@@ -0,0 +1,98 @@
+use crate::{events, Glob};
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Keeps track of whether the server is stopping and how many requests are being handled
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    stopping: AtomicBool,
+    in_flight: AtomicUsize,
+    /// Notified whenever the last in-flight request finishes
+    idle: Notify,
+}
+
+/// Counts as an in-flight request until it's dropped
+pub struct RequestGuard<'a>(&'a Shutdown);
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify();
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn is_stopping(&self) -> bool { self.stopping.load(Ordering::SeqCst) }
+
+    /// Returns false if the server was already stopping
+    pub fn stop(&self) -> bool { !self.stopping.swap(true, Ordering::SeqCst) }
+
+    /// Counts the request as in flight, unless the server is stopping
+    pub fn request(&self) -> Option<RequestGuard<'_>> {
+        if self.is_stopping() {
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = RequestGuard(self);
+        // Stopping might have started in between, the guard gets dropped right away then
+        if self.is_stopping() {
+            return None;
+        }
+        Some(guard)
+    }
+
+    pub fn in_flight(&self) -> usize { self.in_flight.load(Ordering::SeqCst) }
+
+    /// Waits until there are no requests being handled, returns false if that took too long
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        let idle = async {
+            // A notification sent before we start waiting is kept, so none get lost in between
+            while self.in_flight() > 0 {
+                self.idle.notified().await;
+            }
+        };
+        tokio::time::timeout(timeout, idle).await.is_ok()
+    }
+}
+
+fn secs_from_env(name: &str, default: u64) -> Duration {
+    let secs = std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default);
+    Duration::from_secs(secs)
+}
+
+/// Stops new logins, tells everyone the server is restarting and gives their clients
+/// `SHUTDOWN_GRACE` seconds (5 by default) to pick that up. Clients are told to reconnect
+/// after `RESTART_DELAY` seconds (30 by default). Waits up to `SHUTDOWN_TIMEOUT` seconds
+/// (10 by default) for the requests being handled, saves what's left and logs everyone out.
+/// The server should stop accepting connections afterwards and call `finish`
+pub async fn begin(glob: &Glob) {
+    if !glob.shutdown.stop() {
+        return;
+    }
+    let grace = secs_from_env("SHUTDOWN_GRACE", 5);
+    let restart_delay = secs_from_env("RESTART_DELAY", 30);
+    let timeout = secs_from_env("SHUTDOWN_TIMEOUT", 10);
+    info!(?grace, ?restart_delay, "shutting down");
+    events::shutdown::announce(restart_delay, glob).await;
+    // Polls only drain the queues now, so this is the clients' window to get the announcement
+    tokio::time::delay_for(grace).await;
+    if !glob.shutdown.wait_idle(timeout).await {
+        warn!(in_flight = glob.shutdown.in_flight(), "requests didn't finish in time");
+    }
+    events::shutdown::save_all(glob).await;
+    events::shutdown::disconnect_all(glob).await;
+}
+
+/// Closes the database connections once the server stopped, `begin` already saved everything
+pub async fn finish(glob: &Glob) {
+    glob.db_pool.close().await;
+    info!("shutdown finished");
+}
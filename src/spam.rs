@@ -0,0 +1,209 @@
+use crate::{events::silence::MAX_SILENCE, limiter::RateLimiter, Glob};
+use std::{borrow::Cow, fmt, str::FromStr, sync::Arc, time::Duration};
+use tracing::{debug, warn};
+
+/// How often the limits of users who stopped talking get dropped
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What happens to messages containing a filtered word
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// The word gets replaced with asterisks
+    Replace,
+    /// The whole message gets dropped
+    Reject,
+}
+
+impl FromStr for FilterMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FilterMode, ()> {
+        match s.to_lowercase().as_str() {
+            "replace" => Ok(FilterMode::Replace),
+            "reject" => Ok(FilterMode::Reject),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpamError {
+    TooLong { max: usize },
+    Filtered,
+    /// The user sent too many messages, `silence` is set once they've done it too many times
+    Flood { silence: bool },
+}
+
+impl fmt::Display for SpamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpamError::TooLong { max } => write!(f, "Messages can't be longer than {}", max),
+            SpamError::Filtered => write!(f, "Your message contains a filtered word"),
+            SpamError::Flood { .. } => write!(f, "You're sending messages too quickly"),
+        }
+    }
+}
+
+/// Checks every chat message before it gets sent anywhere
+#[derive(Debug)]
+pub struct SpamFilter {
+    /// Messages allowed per user in a window of time
+    pub messages: RateLimiter<i32>,
+    /// Floods allowed per user before they get silenced
+    pub floods: RateLimiter<i32>,
+    /// How long flooding silences for, in seconds, at most `MAX_SILENCE`
+    pub flood_silence: u64,
+    pub max_length: usize,
+    /// Lowercase words matched anywhere in the message, ignoring case (including non-ASCII)
+    pub words: Vec<String>,
+    pub mode: FilterMode,
+}
+
+impl Default for SpamFilter {
+    fn default() -> Self {
+        SpamFilter {
+            messages: RateLimiter::new(10, Duration::from_secs(5)),
+            floods: RateLimiter::new(3, Duration::from_secs(60)),
+            flood_silence: 10 * 60,
+            max_length: 450,
+            words: Vec::new(),
+            mode: FilterMode::Replace,
+        }
+    }
+}
+
+fn var<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+impl SpamFilter {
+    /// Reads `CHAT_MAX_MESSAGES` and `CHAT_MESSAGE_WINDOW` (in seconds),
+    /// `CHAT_MAX_FLOODS`, `CHAT_FLOOD_WINDOW` and `CHAT_FLOOD_SILENCE` (in seconds),
+    /// `CHAT_MAX_LENGTH`, `CHAT_FILTER` (comma separated words)
+    /// and `CHAT_FILTER_MODE` (`replace` or `reject`),
+    /// anything missing falls back to the defaults
+    pub fn from_env() -> Self {
+        let default = SpamFilter::default();
+        let messages = RateLimiter::new(
+            var("CHAT_MAX_MESSAGES").unwrap_or(default.messages.max_hits),
+            var("CHAT_MESSAGE_WINDOW")
+                .map(Duration::from_secs)
+                .unwrap_or(default.messages.window),
+        );
+        let floods = RateLimiter::new(
+            var("CHAT_MAX_FLOODS").unwrap_or(default.floods.max_hits),
+            var("CHAT_FLOOD_WINDOW")
+                .map(Duration::from_secs)
+                .unwrap_or(default.floods.window),
+        );
+        let words = std::env::var("CHAT_FILTER")
+            .map(|v| {
+                v.split(',')
+                    .map(|w| w.trim().to_lowercase())
+                    .filter(|w| !w.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        SpamFilter {
+            messages,
+            floods,
+            flood_silence: var("CHAT_FLOOD_SILENCE")
+                .map(|s: u64| s.min(MAX_SILENCE))
+                .unwrap_or(default.flood_silence),
+            max_length: var("CHAT_MAX_LENGTH").unwrap_or(default.max_length),
+            words,
+            mode: var("CHAT_FILTER_MODE").unwrap_or(default.mode),
+        }
+    }
+
+    /// Replaces every filtered word with asterisks, returns `None` if there weren't any
+    pub fn censor(&self, content: &str) -> Option<String> {
+        let mut res = String::with_capacity(content.len());
+        let mut found = false;
+        let mut i = 0;
+        'outer: while i < content.len() {
+            for w in self.words.iter() {
+                if let Some(len) = prefix_len(&content[i..], w) {
+                    let matched = &content[i..i + len];
+                    res.extend(std::iter::repeat('*').take(matched.chars().count()));
+                    found = true;
+                    i += len;
+                    continue 'outer;
+                }
+            }
+            let c = content[i..].chars().next().unwrap();
+            res.push(c);
+            i += c.len_utf8();
+        }
+        if found {
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    /// Counts the message towards the user's limit and filters it,
+    /// returning what should actually be sent
+    pub async fn check<'a>(&self, id: i32, content: &'a str) -> Result<Cow<'a, str>, SpamError> {
+        if self.messages.is_limited(&id).await {
+            let silence = self.floods.hit(id).await;
+            if silence {
+                warn!(id, "user keeps flooding the chat");
+                self.floods.reset(&id).await;
+            } else {
+                debug!(id, "message dropped for flooding");
+            }
+            return Err(SpamError::Flood { silence });
+        }
+        self.messages.hit(id).await;
+        if content.chars().count() > self.max_length {
+            debug!(id, len = content.len(), "message too long");
+            return Err(SpamError::TooLong {
+                max: self.max_length,
+            });
+        }
+        match (self.censor(content), self.mode) {
+            (None, _) => Ok(Cow::Borrowed(content)),
+            (Some(censored), FilterMode::Replace) => {
+                debug!(id, "filtered words replaced");
+                Ok(Cow::Owned(censored))
+            }
+            (Some(_), FilterMode::Reject) => {
+                debug!(id, "message rejected by the word filter");
+                Err(SpamError::Filtered)
+            }
+        }
+    }
+
+    /// Drops limits which already expired, logging out doesn't reset them
+    /// so that it can't be used to get around the flood protection
+    pub async fn cleanup(&self) {
+        self.messages.cleanup().await;
+        self.floods.cleanup().await;
+    }
+}
+
+/// Runs `SpamFilter::cleanup` every `CLEANUP_INTERVAL`
+pub async fn cleanup_forever(glob: Arc<Glob>) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        glob.spam_filter.cleanup().await;
+    }
+}
+
+/// Length in bytes of the start of `s` which lowercases to `word`
+fn prefix_len(s: &str, word: &str) -> Option<usize> {
+    let mut word = word.chars();
+    for (i, c) in s.char_indices() {
+        for lower in c.to_lowercase() {
+            if word.next() != Some(lower) {
+                return None;
+            }
+        }
+        if word.as_str().is_empty() {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
@@ -46,7 +46,16 @@ impl Token for DummyToken {
 
     async fn enqueue_vec(&self, buf: Vec<u8>) { () }
 
-    async fn join_channel(&self, ch: Weak<Channel>) { self.channels.write().await.push(ch); }
+    async fn join_channel(&self, ch: Weak<Channel>) {
+        let mut channels = self.channels.write().await;
+        channels.retain(|c| c.strong_count() > 0);
+        channels.push(ch);
+    }
+
+    async fn part_channel(&self, ch: &Channel) {
+        let mut channels = self.channels.write().await;
+        channels.retain(|c| c.upgrade().map_or(false, |c| !std::ptr::eq(&*c, ch)));
+    }
 
     async fn channels(&self) -> RwLockReadGuard<'_, Vec<Weak<Channel>>> {
         self.channels.read().await
@@ -1,6 +1,6 @@
 pub mod dummy;
 pub mod player;
-use crate::Channel;
+use crate::{privileges::Privileges, Channel};
 use async_trait::async_trait;
 use core::{
     fmt,
@@ -36,8 +36,10 @@ pub trait Token: Send + Sync {
     // type EnqueueVec<'a>: Future<Output=()> + 'a;
     // fn enqueue_vec(&self, buf: Vec<u8>) -> Self::EnqueueVec<'_>;
     async fn join_channel(&self, ch: Weak<Channel>);
+    async fn part_channel(&self, ch: &Channel);
     async fn channels(&self) -> RwLockReadGuard<'_, Vec<Weak<Channel>>>;
     fn as_player(&self) -> Option<&player::PlayerToken> { None }
+    fn privileges(&self) -> Privileges { Privileges::default() }
     async fn clear_queue(&self) -> Vec<u8> { Vec::new() }
 }
 
@@ -1,9 +1,12 @@
 use super::Token;
-use crate::{Channel, Glob, Match};
+use crate::{privileges::Privileges, Channel, Glob, Match};
 use async_trait::async_trait;
 use std::{
     collections::HashMap,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Weak,
+    },
     time::Duration,
 };
 use tokio::{
@@ -85,6 +88,28 @@ impl Default for Stats {
     fn default() -> Self { Stats::new() }
 }
 
+impl Stats {
+    pub fn set_mode_stats(&mut self, stats: &ModeStats) {
+        self.ranked_score = stats.ranked_score;
+        self.accuracy = stats.accuracy;
+        self.playcount = stats.playcount;
+        self.total_score = stats.total_score;
+        self.rank = stats.rank;
+        self.pp = stats.pp;
+    }
+}
+
+/// Part of the stats which is different for every game mode
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ModeStats {
+    pub ranked_score: u64,
+    pub accuracy: f32,
+    pub playcount: u32,
+    pub total_score: u64,
+    pub rank: u32,
+    pub pp: u16,
+}
+
 #[derive(Debug)]
 pub struct PlayerToken {
     pub id: i32,
@@ -92,8 +117,22 @@ pub struct PlayerToken {
     pub username: String,
     pub queue: Mutex<Vec<u8>>,
     pub stats: RwLock<Stats>,
+    pub mode_stats: RwLock<[ModeStats; 4]>,
     pub channels: RwLock<Vec<Weak<Channel>>>,
     pub multi: Mutex<Option<Weak<Match>>>,
+    pub spectators: RwLock<Vec<Arc<dyn Token>>>,
+    pub spectating: Mutex<Option<Weak<dyn Token>>>,
+    pub friends: RwLock<Vec<i32>>,
+    pub block_non_friends: AtomicBool,
+    /// Unix time at which the user can talk again
+    pub silence_end: AtomicU64,
+    pub privileges: AtomicU32,
+    /// Set once the user got kicked, the token gets dropped after their next poll
+    /// so that they still receive the reason
+    pub kicked: AtomicBool,
+    /// Newest offline message sent in the login response, it only counts as delivered
+    /// once the client polls with its token, since that proves the response arrived
+    pub undelivered: Mutex<Option<i32>>,
     pub sender: Option<Mutex<mpsc::Sender<&'static str>>>,
 }
 
@@ -110,8 +149,17 @@ impl PlayerToken {
             queue: Mutex::new(Vec::new()),
             username,
             stats: RwLock::default(),
+            mode_stats: RwLock::default(),
             channels: RwLock::default(),
             multi: Mutex::default(),
+            spectators: RwLock::default(),
+            spectating: Mutex::default(),
+            friends: RwLock::default(),
+            block_non_friends: AtomicBool::default(),
+            silence_end: AtomicU64::default(),
+            privileges: AtomicU32::new(Privileges::NORMAL.0),
+            kicked: AtomicBool::default(),
+            undelivered: Mutex::default(),
             sender: None,
         };
         let res = Arc::new(res);
@@ -134,8 +182,17 @@ impl PlayerToken {
             queue: Mutex::new(Vec::new()),
             username,
             stats: RwLock::default(),
+            mode_stats: RwLock::default(),
             channels: RwLock::default(),
             multi: Mutex::default(),
+            spectators: RwLock::default(),
+            spectating: Mutex::default(),
+            friends: RwLock::default(),
+            block_non_friends: AtomicBool::default(),
+            silence_end: AtomicU64::default(),
+            privileges: AtomicU32::new(Privileges::NORMAL.0),
+            kicked: AtomicBool::default(),
+            undelivered: Mutex::default(),
             sender: Some(Mutex::new(sender)),
         };
         let res = Arc::new(res);
@@ -156,6 +213,22 @@ impl PlayerToken {
         });
         res
     }
+
+    pub async fn is_friend(&self, id: i32) -> bool { self.friends.read().await.contains(&id) }
+
+    /// Seconds left until the silence ends, 0 if the user isn't silenced
+    pub fn silence_remaining(&self) -> u64 {
+        let end = self.silence_end.load(Ordering::SeqCst);
+        end.saturating_sub(crate::events::silence::now())
+    }
+
+    /// Replaces stats of every game mode and applies the ones for the current mode
+    pub async fn update_mode_stats(&self, all: [ModeStats; 4]) {
+        *self.mode_stats.write().await = all;
+        let mut stats = self.stats.write().await;
+        let mode = stats.game_mode as usize;
+        stats.set_mode_stats(&all[mode]);
+    }
 }
 
 #[async_trait]
@@ -172,7 +245,17 @@ impl Token for PlayerToken {
 
     fn username(&self) -> &str { &self.username }
 
-    async fn join_channel(&self, ch: Weak<Channel>) { self.channels.write().await.push(ch) }
+    async fn join_channel(&self, ch: Weak<Channel>) {
+        let mut channels = self.channels.write().await;
+        // Forget channels which were removed in the meantime, like ones of closed matches
+        channels.retain(|c| c.strong_count() > 0);
+        channels.push(ch)
+    }
+
+    async fn part_channel(&self, ch: &Channel) {
+        let mut channels = self.channels.write().await;
+        channels.retain(|c| c.upgrade().map_or(false, |c| !std::ptr::eq(&*c, ch)));
+    }
 
     async fn enqueue(&self, buf: &[u8]) { self.queue.lock().await.extend_from_slice(buf) }
 
@@ -184,6 +267,8 @@ impl Token for PlayerToken {
 
     fn as_player(&self) -> Option<&PlayerToken> { Some(self) }
 
+    fn privileges(&self) -> Privileges { Privileges(self.privileges.load(Ordering::SeqCst)) }
+
     async fn clear_queue(&self) -> Vec<u8> {
         let mut m = self.queue.lock().await;
         let mut res = Vec::with_capacity(m.len());
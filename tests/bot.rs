@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use isoku::{
+    bot::{handle_command, ArgError, Args, Command, CommandResult, Commands, Context},
+    events::ErrorKind,
+    packets::server::dispose_match,
+    privileges::Privileges,
+    r#match::SlotStatus,
+    token::Token,
+    Glob, PlayerToken,
+};
+
+#[test]
+fn args() {
+    let mut args = Args::new(" peppy  10m spamming in   #osu");
+    assert_eq!(args.next("user"), Ok("peppy"));
+    assert_eq!(
+        args.parse::<u32>("duration"),
+        Err(ArgError::Invalid {
+            name: "duration",
+            value: "10m".to_string()
+        })
+    );
+    assert_eq!(args.rest(), "spamming in #osu");
+    assert_eq!(args.next("user"), Err(ArgError::Missing("user")));
+    assert_eq!(args.optional::<u32>("size"), Ok(None));
+}
+
+struct Secret;
+
+#[async_trait]
+impl Command for Secret {
+    fn name(&self) -> &'static str { "secret" }
+
+    fn aliases(&self) -> &'static [&'static str] { &["s"] }
+
+    fn description(&self) -> &'static str { "Only for admins" }
+
+    fn privileges(&self) -> Privileges { Privileges::ADMIN }
+
+    async fn run(&self, _args: Args<'_>, _ctx: &Context<'_>) -> CommandResult { Ok(None) }
+}
+
+#[test]
+fn registry() {
+    let mut commands = Commands::builtin();
+    commands.register(Secret);
+    assert_eq!(commands.find("s").map(|c| c.name()), Some("secret"));
+    assert!(commands.find("nope").is_none());
+    let help = commands.help(Privileges::NORMAL);
+    assert!(help.contains("!echo <text>"));
+    assert!(!help.contains("!kick"));
+    assert!(!help.contains("!secret"));
+    let help = commands.help(Privileges::ADMIN);
+    assert!(help.contains("!kick <user> [reason]"));
+    assert!(help.contains("!secret - Only for admins"));
+}
+
+#[test]
+fn mods() {
+    use isoku::bot::multiplayer::parse_mods;
+    assert_eq!(parse_mods("72"), Some((72, false)));
+    assert_eq!(parse_mods("hd dt"), Some((72, false)));
+    assert_eq!(parse_mods("HR FM"), Some((16, true)));
+    assert_eq!(parse_mods("XX"), None);
+}
+
+#[tokio::test]
+async fn referee() {
+    dotenv::dotenv().ok();
+    let glob = Glob::new().await;
+    let (referee, player, other) = {
+        let mut list = glob.token_list.write().await;
+        (
+            PlayerToken::new(&mut list, 10, "referee".to_string()),
+            PlayerToken::new(&mut list, 11, "player".to_string()),
+            PlayerToken::new(&mut list, 12, "other".to_string()),
+        )
+    };
+    let err = handle_command("mp lock", &referee, &glob).await.unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Permission);
+    let err = handle_command("mp make test match", &referee, &glob).await.unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Permission);
+    referee
+        .as_player()
+        .unwrap()
+        .privileges
+        .store(Privileges::MODERATOR.0, std::sync::atomic::Ordering::SeqCst);
+
+    handle_command("mp make test match", &referee, &glob).await.unwrap();
+    let multi = glob.match_list.read().await.values().next().unwrap().clone();
+    assert!(multi.is_empty());
+    assert!(multi.is_referee(referee.id()).await);
+
+    assert_eq!(multi.join(&player).await, Some(0));
+    handle_command("mp size 4", &referee, &glob).await.unwrap();
+    assert_eq!(multi.slot_statuses()[3], SlotStatus::Free);
+    assert_eq!(multi.slot_statuses()[4], SlotStatus::Locked);
+    handle_command("mp move player 3", &referee, &glob).await.unwrap();
+    assert_eq!(multi.slot_of(player.id()).await, Some(2));
+    handle_command("mp host player", &referee, &glob).await.unwrap();
+    assert_eq!(*multi.host_id.read().await, player.id());
+    handle_command("mp mods HD FM", &referee, &glob).await.unwrap();
+    assert_eq!(multi.slot_mods(), Some([0; 16]));
+
+    let err = handle_command("mp lock", &other, &glob).await.unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Permission);
+
+    glob.lobby.write().await.push(other.clone());
+    other.clear_queue().await;
+    player.clear_queue().await;
+    handle_command("mp close", &referee, &glob).await.unwrap();
+    assert!(glob.match_list.read().await.is_empty());
+    // Both the lobby and the players get told exactly once
+    let dispose = dispose_match(multi.id);
+    let count = |queue: Vec<u8>| {
+        queue
+            .windows(dispose.len())
+            .filter(|w| *w == &dispose[..])
+            .count()
+    };
+    assert_eq!(count(other.clear_queue().await), 1);
+    assert_eq!(count(player.clear_queue().await), 1);
+}
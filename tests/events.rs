@@ -1,5 +1,8 @@
 #![feature(option_expect_none)]
-use isoku::{events as e, packets::OsuEncode, token::Token, Channel, Glob, PlayerToken};
+use isoku::{
+    events as e, packets::OsuEncode, privileges::Privileges, token::Token, Channel, Glob, Match,
+    PlayerToken,
+};
 use std::sync::Arc;
 
 async fn setup() -> Glob {
@@ -7,6 +10,24 @@ async fn setup() -> Glob {
     Glob::new().await
 }
 
+/// Logs in a host with id 1 and another player with id 2, the host gets a new match
+async fn setup_match(glob: &Glob, other: &str) -> (Arc<dyn Token>, Arc<dyn Token>, Arc<Match>) {
+    let (host, other) = {
+        let mut list = glob.token_list.write().await;
+        let host = PlayerToken::new(&mut list, 1, "host".to_string());
+        let other = PlayerToken::new(&mut list, 2, other.to_string());
+        (host, other)
+    };
+    let m = {
+        let mut list = glob.match_list.write().await;
+        Match::new(&mut list, "test", "", "", 0, "", &host)
+            .await
+            .unwrap()
+    };
+    *host.as_player().unwrap().multi.lock().await = Some(Arc::downgrade(&m));
+    (host, other, m)
+}
+
 #[tokio::test]
 async fn logout() {
     let glob = setup().await;
@@ -50,3 +71,398 @@ async fn channel_join() {
     let ch = user_channels.first().unwrap().upgrade().unwrap();
     assert_eq!(ch.name, "test");
 }
+
+#[tokio::test]
+async fn match_join_part() {
+    let glob = setup().await;
+    let (host, guest, m) = setup_match(&glob, "guest").await;
+    let mut event_data = Vec::new();
+    (m.id as i32).encode(&mut event_data);
+    "".encode(&mut event_data);
+    // Ids out of the u16 range mustn't wrap around into existing matches
+    let mut wrapped = Vec::new();
+    (m.id as i32 + 65536).encode(&mut wrapped);
+    "".encode(&mut wrapped);
+    let err = e::matches::join(&wrapped, &guest, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::NotFound);
+    // Rejoining the match you're alone in mustn't dispose of it
+    e::matches::join(&event_data, &host, &glob).await.unwrap();
+    assert!(glob.match_list.read().await.get(&m.id).is_some());
+    e::matches::join(&event_data, &guest, &glob).await.unwrap();
+    assert_eq!(m.first_player().await.unwrap().id(), 1);
+    assert!(guest.as_player().unwrap().multi.lock().await.is_some());
+
+    e::matches::part(&host, &glob).await.unwrap();
+    assert_eq!(*m.host_id.read().await, 2);
+    e::matches::part(&guest, &glob).await.unwrap();
+    assert!(m.is_empty());
+    assert!(glob.match_list.read().await.get(&m.id).is_none());
+}
+
+#[test]
+fn silence_duration() {
+    assert_eq!(e::silence::parse_duration("90"), Some(90));
+    assert_eq!(e::silence::parse_duration("10m"), Some(600));
+    assert_eq!(e::silence::parse_duration("2h"), Some(7200));
+    assert_eq!(e::silence::parse_duration("1d"), Some(86400));
+    assert_eq!(e::silence::parse_duration("1y"), None);
+    assert_eq!(e::silence::parse_duration("m"), None);
+}
+
+#[tokio::test]
+async fn silenced_message() {
+    use std::sync::atomic::Ordering;
+    let glob = setup().await;
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 0, "nrabulinski".to_string())
+    };
+    let mut channel = Vec::new();
+    "#osu".encode(&mut channel);
+    e::channel_join::handle(&channel, &token, &glob).await.unwrap();
+    let mut data = Vec::new();
+    "".encode(&mut data);
+    "hello".encode(&mut data);
+    "#osu".encode(&mut data);
+    e::send_message::public(&data, &token, &glob).await.unwrap();
+    let player = token.as_player().unwrap();
+    player.silence_end.store(e::silence::now() + 60, Ordering::SeqCst);
+    assert!(player.silence_remaining() > 0);
+    let err = e::send_message::public(&data, &token, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+    let err = e::silence::silence("nrabulinski", u64::MAX, "", glob.bot.as_ref(), &glob)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Invalid);
+}
+
+#[tokio::test]
+async fn silence_admin() {
+    use std::sync::atomic::Ordering;
+    let glob = setup().await;
+    let (user, target) = {
+        let mut list = glob.token_list.write().await;
+        let user = PlayerToken::new(&mut list, 0, "nrabulinski".to_string());
+        let target = PlayerToken::new(&mut list, 1, "wojexe".to_string());
+        (user, target)
+    };
+    user.as_player().unwrap().privileges.store(Privileges::MODERATOR.0, Ordering::SeqCst);
+    target.as_player().unwrap().privileges.store(Privileges::ADMIN.0, Ordering::SeqCst);
+    let err = e::silence::silence("wojexe", 60, "", user.as_ref(), &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+    let err = e::silence::unsilence("wojexe", user.as_ref(), &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+    assert_eq!(target.as_player().unwrap().silence_end.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn kick_permissions() {
+    use isoku::packets::server::login_failed;
+    let glob = setup().await;
+    let (user, target) = {
+        let mut list = glob.token_list.write().await;
+        let user = PlayerToken::new(&mut list, 0, "nrabulinski".to_string());
+        let target = PlayerToken::new(&mut list, 1, "wojexe".to_string());
+        (user, target)
+    };
+    let err = isoku::bot::handle_command("kick wojexe", &user, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+    user.as_player()
+        .unwrap()
+        .privileges
+        .store(Privileges::MODERATOR.0, std::sync::atomic::Ordering::SeqCst);
+    let err = isoku::bot::handle_command("kick", &user, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Invalid);
+    assert!(glob.token_list.read().await.contains_key(target.token()));
+    isoku::bot::handle_command("kick wojexe", &user, &glob).await.unwrap();
+    // The token sticks around until the reason gets delivered
+    assert!(glob.token_list.read().await.contains_key(target.token()));
+    let target = target.as_player().unwrap();
+    assert!(target.kicked.load(std::sync::atomic::Ordering::SeqCst));
+    assert!(target.queue.lock().await.ends_with(&login_failed()));
+}
+
+#[tokio::test]
+async fn channel_history() {
+    use isoku::channel::{HistoryEntry, HISTORY_SIZE};
+    let glob = setup().await;
+    let test_channel = {
+        let mut list = glob.channel_list.write().await;
+        Channel::new(&mut list, "test", "", false)
+    };
+    for i in 0..HISTORY_SIZE + 5 {
+        test_channel
+            .push_history(HistoryEntry {
+                from: "peppy".to_string(),
+                from_id: 2,
+                content: i.to_string(),
+            })
+            .await;
+    }
+    let history = test_channel.history().await;
+    assert_eq!(history.len(), HISTORY_SIZE);
+    assert_eq!(history[0].content, "5");
+
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 0, "nrabulinski".to_string())
+    };
+    let mut data = Vec::new();
+    "test".encode(&mut data);
+    e::channel_join::handle(&data, &token, &glob).await.unwrap();
+    let queue = token.clear_queue().await;
+    let last = isoku::packets::server::message_from("peppy", 2, "test", "54");
+    assert!(queue.ends_with(&last));
+}
+
+#[tokio::test]
+async fn channel_privileges() {
+    use isoku::channel::ChannelDef;
+    use std::sync::atomic::Ordering;
+    let glob = setup().await;
+    {
+        let mut list = glob.channel_list.write().await;
+        let def = ChannelDef {
+            name: "#staff".to_string(),
+            desc: String::new(),
+            auto_join: false,
+            read_privileges: "moderator".parse().unwrap(),
+            write_privileges: "admin".parse().unwrap(),
+        };
+        Channel::from_def(&mut list, def);
+    }
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 0, "nrabulinski".to_string())
+    };
+    let mut channel = Vec::new();
+    "#staff".encode(&mut channel);
+    let err = e::channel_join::handle(&channel, &token, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+
+    let player = token.as_player().unwrap();
+    player.privileges.store(Privileges::MODERATOR.0, Ordering::SeqCst);
+    e::channel_join::handle(&channel, &token, &glob).await.unwrap();
+    let mut data = Vec::new();
+    "".encode(&mut data);
+    "hello".encode(&mut data);
+    "#staff".encode(&mut data);
+    let err = e::send_message::public(&data, &token, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+
+    player.privileges.store(Privileges::ADMIN.0, Ordering::SeqCst);
+    e::send_message::public(&data, &token, &glob).await.unwrap();
+}
+
+#[tokio::test]
+async fn channel_user_counts() {
+    use isoku::packets::server::channel_info;
+    let glob = setup().await;
+    let test_channel = {
+        let mut list = glob.channel_list.write().await;
+        Channel::new(&mut list, "#test", "", true)
+    };
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 0, "nrabulinski".to_string())
+    };
+    e::channels::update_counts(&glob).await;
+    token.clear_queue().await;
+
+    let mut data = Vec::new();
+    "#test".encode(&mut data);
+    e::channel_join::handle(&data, &token, &glob).await.unwrap();
+    token.clear_queue().await;
+    e::channels::update_counts(&glob).await;
+    let queue = token.clear_queue().await;
+    assert!(queue.ends_with(&channel_info(&test_channel).await));
+
+    e::channels::update_counts(&glob).await;
+    assert!(token.clear_queue().await.is_empty());
+}
+
+#[tokio::test]
+async fn lobby_updates() {
+    use isoku::packets::server::{dispose_match, lobby_update_match};
+    let glob = setup().await;
+    let (host, watcher, m) = setup_match(&glob, "watcher").await;
+    e::lobby_join::handle(&watcher, &glob).await.unwrap();
+    watcher.clear_queue().await;
+
+    e::matches::ready(host.as_ref(), &glob).await.unwrap();
+    assert_eq!(watcher.clear_queue().await, lobby_update_match(&m).await);
+    e::matches::start(host.as_ref(), &glob).await.unwrap();
+    assert!(m.in_progress.load(std::sync::atomic::Ordering::SeqCst));
+    assert_eq!(watcher.clear_queue().await, lobby_update_match(&m).await);
+    e::matches::part(&host, &glob).await.unwrap();
+    assert_eq!(watcher.clear_queue().await, dispose_match(m.id));
+}
+
+#[tokio::test]
+async fn match_host_controls() {
+    use isoku::packets::server::dispose_match;
+    use std::sync::atomic::Ordering;
+    let glob = setup().await;
+    let (host, guest, m) = setup_match(&glob, "guest").await;
+    let mut data = Vec::new();
+    (m.id as i32).encode(&mut data);
+    "".encode(&mut data);
+    e::matches::join(&data, &guest, &glob).await.unwrap();
+
+    let mut slot = Vec::new();
+    1i32.encode(&mut slot);
+    let err = e::matches::transfer_host(&slot, guest.as_ref(), &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Permission);
+    e::matches::transfer_host(&slot, host.as_ref(), &glob).await.unwrap();
+    assert_eq!(*m.host_id.read().await, 2);
+
+    m.freemod.store(true, Ordering::SeqCst);
+    let mut mods = Vec::new();
+    (8i32 | 64).encode(&mut mods);
+    e::matches::change_mods(&mods, guest.as_ref(), &glob).await.unwrap();
+    assert_eq!(m.mods.load(Ordering::SeqCst), 64);
+    assert_eq!(m.slots[1].mods.load(Ordering::SeqCst), 8);
+
+    // Locking an occupied slot kicks the player out of it
+    let mut slot = Vec::new();
+    0i32.encode(&mut slot);
+    host.clear_queue().await;
+    e::matches::lock(&slot, guest.as_ref(), &glob).await.unwrap();
+    assert_eq!(m.slot_of(1).await, None);
+    assert!(host.as_player().unwrap().multi.lock().await.is_none());
+    assert!(host.clear_queue().await.starts_with(&dispose_match(m.id)));
+}
+
+#[tokio::test]
+async fn match_password() {
+    use isoku::{
+        packets::{
+            packet_data,
+            server::{lobby_update_match, update_match},
+            OsuDecode,
+        },
+        r#match::MatchInfo,
+    };
+    let glob = setup().await;
+    let (host, watcher, m) = setup_match(&glob, "watcher").await;
+    e::lobby_join::handle(&watcher, &glob).await.unwrap();
+    watcher.clear_queue().await;
+
+    let data = update_match(&m).await;
+    let (mut info, _) = MatchInfo::decode(packet_data(&data).unwrap().1).unwrap();
+    info.password = "secret";
+    let mut data = Vec::new();
+    info.encode(&mut data);
+    e::matches::change_password(&data, host.as_ref(), &glob).await.unwrap();
+    assert_eq!(m.password.read().await.as_deref(), Some("secret"));
+
+    let lobby = watcher.clear_queue().await;
+    assert_eq!(lobby, lobby_update_match(&m).await);
+    let (info, _) = MatchInfo::decode(packet_data(&lobby).unwrap().1).unwrap();
+    assert!(!info.password.is_empty());
+    assert_ne!(info.password, "secret");
+}
+
+#[tokio::test]
+async fn logout_disposes_match() {
+    use isoku::packets::packet_data;
+    let glob = setup().await;
+    let (host, watcher) = {
+        let mut list = glob.token_list.write().await;
+        let host = PlayerToken::new(&mut list, 1, "host".to_string());
+        let watcher = PlayerToken::new(&mut list, 2, "watcher".to_string());
+        (host, watcher)
+    };
+    let settings = {
+        let template = Match::new_empty(&mut Default::default(), "test").unwrap();
+        isoku::packets::server::update_match(&template).await
+    };
+    let (_, settings) = packet_data(&settings).unwrap();
+    e::matches::create(settings, &host, &glob).await.unwrap();
+    let m = glob.match_list.read().await.values().next().unwrap().clone();
+    let m_ptr = Arc::downgrade(&m);
+    drop(m);
+    e::lobby_join::handle(&watcher, &glob).await.unwrap();
+
+    let (host_ptr, host) = (Arc::downgrade(&host), host.token().to_owned());
+    e::logout::handle(&host, &glob).await.unwrap();
+    assert!(glob.match_list.read().await.is_empty());
+    m_ptr.upgrade().expect_none("Match not dropped");
+    host_ptr.upgrade().expect_none("Token not dropped");
+
+    e::logout::handle(watcher.token(), &glob).await.unwrap();
+    assert!(glob.lobby.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn shutdown() {
+    use isoku::packets::server::server_restart;
+    use std::time::Duration;
+    let glob = setup().await;
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 0, "nrabulinski".to_string())
+    };
+    let request = glob.shutdown.request().unwrap();
+    assert_eq!(glob.shutdown.in_flight(), 1);
+    assert!(glob.shutdown.stop());
+    assert!(!glob.shutdown.stop());
+    // Requests which started before stopping are waited for, new ones aren't counted
+    assert!(glob.shutdown.request().is_none());
+    assert_eq!(glob.shutdown.in_flight(), 1);
+    assert!(!glob.shutdown.wait_idle(Duration::from_millis(10)).await);
+    drop(request);
+    assert!(glob.shutdown.wait_idle(Duration::from_secs(1)).await);
+
+    e::shutdown::announce(Duration::from_secs(30), &glob).await;
+    assert!(token.clear_queue().await.ends_with(&server_restart(30_000)));
+    let player = token.as_player().unwrap();
+    player.block_non_friends.store(true, std::sync::atomic::Ordering::SeqCst);
+    e::shutdown::save_all(&glob).await;
+    assert!(e::friends::load_block_non_friends(0, &glob).await.unwrap());
+    e::shutdown::disconnect_all(&glob).await;
+    assert!(!glob.token_list.read().await.contains_key(token.token()));
+    assert!(glob.token_list.read().await.contains_key(glob.bot.token()));
+}
+
+#[tokio::test]
+async fn friend_add_unknown_user() {
+    let glob = setup().await;
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 0, "nrabulinski".to_string())
+    };
+    let mut data = Vec::new();
+    (-1i32).encode(&mut data);
+    let err = e::friends::add(&data, token.as_ref(), &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::NotFound);
+    assert!(token.as_player().unwrap().friends.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn empty_match_name() {
+    use isoku::packets::{packet_data, server::update_match};
+    let glob = setup().await;
+    let token = {
+        let mut list = glob.token_list.write().await;
+        PlayerToken::new(&mut list, 1, "host".to_string())
+    };
+    let settings = {
+        let template = Match::new_empty(&mut Default::default(), "").unwrap();
+        update_match(&template).await
+    };
+    let (_, settings) = packet_data(&settings).unwrap();
+    let err = e::matches::create(settings, &token, &glob).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Invalid);
+    assert!(!err.kind.drops_session());
+    assert!(glob.token_list.read().await.contains_key(token.token()));
+    assert!(glob.match_list.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn truncated_score_frame() {
+    let glob = setup().await;
+    let (host, _, _) = setup_match(&glob, "guest").await;
+    let err = e::matches::score_update(&[0, 0, 0, 0], host.as_ref()).await.unwrap_err();
+    assert_eq!(err.kind, e::ErrorKind::Decode);
+}
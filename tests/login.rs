@@ -0,0 +1,67 @@
+use isoku::login::{self, ClientHashes, LoginInfo, LoginLimiter};
+use std::{net::IpAddr, time::Duration};
+
+#[test]
+fn login_info() {
+    let line = "b20200201.2|2|0|d41d8cd98f00b204e9800998ecf8427e:00-00-00-00-00-00.:a:b:c:|1\n";
+    let info = LoginInfo::parse(line).unwrap();
+    assert_eq!(
+        info,
+        LoginInfo {
+            version: "b20200201.2",
+            utc_offset: 2,
+            display_city: false,
+            hashes: ClientHashes {
+                osu_path: "d41d8cd98f00b204e9800998ecf8427e",
+                adapters: "00-00-00-00-00-00.",
+                adapters_md5: "a",
+                uninstall_id: "b",
+                disk_signature: "c",
+            },
+            block_non_friends: true,
+        }
+    );
+    assert!(LoginInfo::parse("b20200201.2|x|0|a:b:c:d:e:|0").is_none());
+    assert!(LoginInfo::parse("b20200201.2|2|0|a:b|0").is_none());
+}
+
+#[tokio::test]
+async fn verify_password() {
+    let md5 = "5f4dcc3b5aa765d61d8327deb882cf99";
+    assert!(login::is_md5(md5));
+    assert!(!login::is_md5("password"));
+    let hash = bcrypt::hash(md5, 4).unwrap();
+    assert!(login::verify_password(md5, Some(&hash)).await);
+    assert!(login::verify_password(&md5.to_uppercase(), Some(&hash)).await);
+    assert!(!login::verify_password("d41d8cd98f00b204e9800998ecf8427e", Some(&hash)).await);
+    assert!(!login::verify_password(md5, None).await);
+}
+
+#[tokio::test]
+async fn lockout() {
+    let limiter = LoginLimiter::new(3, Duration::from_millis(200));
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    let other_ip = IpAddr::from([127, 0, 0, 2]);
+    for _ in 0..3 {
+        assert!(!limiter.is_locked(ip, "user").await);
+        limiter.failed(ip, "User").await;
+    }
+    assert!(limiter.is_locked(ip, "someone").await);
+    assert!(limiter.is_locked(other_ip, "user").await);
+    assert!(!limiter.is_locked(other_ip, "someone").await);
+    tokio::time::delay_for(Duration::from_millis(300)).await;
+    assert!(!limiter.is_locked(ip, "user").await);
+}
+
+#[test]
+fn client_ip() {
+    let mut limiter = LoginLimiter::new(3, Duration::from_secs(1));
+    let remote = IpAddr::from([127, 0, 0, 1]);
+    let mut headers = http::HeaderMap::new();
+    headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+    assert_eq!(limiter.client_ip(&headers, remote), remote);
+    limiter.ip_header = Some("X-Forwarded-For".to_string());
+    let proxied = IpAddr::from([2, 2, 2, 2]);
+    assert_eq!(limiter.client_ip(&headers, remote), proxied);
+    assert_eq!(limiter.client_ip(&http::HeaderMap::new(), remote), remote);
+}
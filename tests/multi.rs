@@ -0,0 +1,74 @@
+use isoku::{
+    r#match::{MatchList, SlotStatus, Team, TeamType},
+    Match, PlayerToken,
+};
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+#[tokio::test]
+async fn game_loop() {
+    let mut tokens = HashMap::new();
+    let host = PlayerToken::new(&mut tokens, 1, "host".to_string());
+    let guest = PlayerToken::new(&mut tokens, 2, "guest".to_string());
+    let mut matches = MatchList::default();
+    let m = Match::new(&mut matches, "test", "", "", 0, "", &host)
+        .await
+        .unwrap();
+    assert_eq!(m.join(&guest).await, Some(1));
+
+    assert!(m.set_ready(1, true));
+    assert!(m.start());
+    assert!(!m.start());
+    assert_eq!(m.slots[1].status.load(Ordering::SeqCst), SlotStatus::Playing as u8);
+
+    assert!(!m.load_complete(0));
+    assert!(m.load_complete(1));
+    assert!(!m.skip(1));
+    assert!(m.skip(0));
+
+    assert!(!m.complete(0));
+    assert!(m.complete(1));
+    assert!(!m.in_progress.load(Ordering::SeqCst));
+    assert_eq!(m.slots[0].status.load(Ordering::SeqCst), SlotStatus::NotReady as u8);
+}
+
+#[tokio::test]
+async fn slots() {
+    let mut tokens = HashMap::new();
+    let host = PlayerToken::new(&mut tokens, 1, "host".to_string());
+    let mut matches = MatchList::default();
+    let m = Match::new(&mut matches, "test", "", "", 0, "", &host)
+        .await
+        .unwrap();
+
+    assert!(m.toggle_lock(1));
+    assert_eq!(m.slot_statuses()[1], SlotStatus::Locked);
+    assert!(!m.move_player(0, 1).await);
+    assert!(m.toggle_lock(1));
+    assert!(!m.toggle_lock(0));
+    assert!(m.move_player(0, 1).await);
+    assert_eq!(m.slot_statuses()[0], SlotStatus::Free);
+    assert_eq!(m.slot_of(1).await, Some(1));
+
+    assert!(!m.is_team_mode());
+    m.team_type.store(TeamType::TeamVs as u8, Ordering::SeqCst);
+    assert!(m.is_team_mode());
+    assert_eq!(m.change_team(1), Team::Red);
+    assert_eq!(m.change_team(1), Team::Blue);
+}
+
+#[tokio::test]
+async fn unique_ids() {
+    let mut tokens = HashMap::new();
+    let host = PlayerToken::new(&mut tokens, 1, "host".to_string());
+    let mut matches = MatchList::default();
+    let first = Match::new(&mut matches, "first", "", "", 0, "", &host)
+        .await
+        .unwrap();
+    let second = Match::new_empty(&mut matches, "second").unwrap();
+    assert_ne!(first.id, second.id);
+    matches.remove(&first.id);
+    let third = Match::new_empty(&mut matches, "third").unwrap();
+    assert_ne!(third.id, second.id);
+    assert_ne!(third.id, first.id);
+    assert_eq!(matches.len(), 2);
+}
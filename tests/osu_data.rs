@@ -1,63 +1,86 @@
-use isoku::packets::OsuEncode;
+use isoku::packets::{DecodeError, OsuDecode, OsuEncode};
 use std::fmt::Debug;
 
-fn basic_test<T>(value: &T, expected_buf: &[u8])
+fn basic_test<T>(value: T, expected_buf: &'static [u8])
 where
-    T: OsuEncode + PartialEq + Debug + ?Sized,
+    T: OsuEncode + OsuDecode<'static> + PartialEq + Debug,
 {
     let mut buf = Vec::with_capacity(value.encoded_size());
     value.encode(&mut buf);
     assert_eq!(expected_buf, &buf[..]);
-    let (decoded, _) = T::decode(&buf).unwrap();
+    let (decoded, off) = T::decode(expected_buf).unwrap();
     assert_eq!(value, decoded);
+    assert_eq!(off, expected_buf.len());
 }
 
 #[test]
 fn string() { basic_test("#osu", &[0xb, 4, 0x23, 0x6f, 0x73, 0x75]) }
 
 #[test]
-fn u16() { basic_test(&2137u16, &[0x59, 0x8]); }
+fn u16() { basic_test(2137u16, &[0x59, 0x8]); }
 
 #[test]
-fn i16() { basic_test(&666i16, &[0x9a, 0x2]); }
+fn i16() { basic_test(666i16, &[0x9a, 0x2]); }
 
 #[test]
-fn u32() { basic_test(&9727u32, &[0xff, 0x25, 0, 0]); }
+fn u32() { basic_test(9727u32, &[0xff, 0x25, 0, 0]); }
 
 #[test]
-fn i32() { basic_test(&1337i32, &[0x39, 0x5, 0, 0]); }
+fn i32() { basic_test(1337i32, &[0x39, 0x5, 0, 0]); }
 
 #[test]
-#[should_panic]
-fn u8_slice_decode() { <[u8] as OsuEncode>::decode(&[0u8]).unwrap(); }
+fn f32() { basic_test(1.5f32, &[0, 0, 0xc0, 0x3f]); }
 
 #[test]
-fn bool() { basic_test(&true, &[1]); }
+fn bool() { basic_test(true, &[1]); }
 
 #[test]
 fn empty_string() { basic_test("", &[0]) }
 
 #[test]
-#[should_panic]
 fn str_unknown_prefix() {
     let buf = [0xc, 0u8];
-    <str as OsuEncode>::decode(&buf).unwrap();
+    assert_eq!(
+        <&str>::decode(&buf).unwrap_err(),
+        DecodeError::UnknownStringPrefix(0xc)
+    );
 }
 
 #[test]
-#[should_panic]
 fn str_invalid_len() {
     let buf = [0xb, 2, 0u8];
-    <str as OsuEncode>::decode(&buf).unwrap();
+    assert_eq!(<&str>::decode(&buf).unwrap_err(), DecodeError::UnexpectedEof);
+}
+
+#[test]
+fn str_truncated_len() {
+    let buf = [0xb, 0x80];
+    assert_eq!(<&str>::decode(&buf).unwrap_err(), DecodeError::UnexpectedEof);
+}
+
+#[test]
+fn str_invalid_utf8() {
+    let buf = [0xb, 1, 0xff];
+    assert_eq!(<&str>::decode(&buf).unwrap_err(), DecodeError::InvalidUtf8);
+}
+
+#[test]
+fn truncated_int() {
+    assert_eq!(i32::decode(&[1, 2, 3]).unwrap_err(), DecodeError::UnexpectedEof);
 }
 
 #[test]
 fn i32_slice() {
     let val = [10i32, 2, 1, 3, 7, 0];
-    basic_test(
-        &val[..],
-        &[
-            6, 0, 10, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0,
-        ],
-    );
+    let expected = [
+        6, 0, 10, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut buf = Vec::with_capacity(val[..].encoded_size());
+    val[..].encode(&mut buf);
+    assert_eq!(&expected[..], &buf[..]);
+    let (decoded, _) = Vec::<i32>::decode(&buf).unwrap();
+    assert_eq!(&val[..], &decoded[..]);
 }
+
+#[test]
+fn i32_array() { basic_test([1i32, -1], &[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]) }
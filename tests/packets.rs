@@ -1,11 +1,14 @@
-use isoku::packets::{server as p, Id, OsuEncode};
+use isoku::{
+    packets::{packet_data, read_packet, server as p, Id, OsuDecode, OsuPacket},
+    r#match::{MatchInfo, ScoringType, SlotIds, SlotStatus, Team, TeamType},
+};
 use std::convert::TryFrom;
 
 fn packet_test(buf: &[u8], expected_id: Id, expected_data: &[u8]) {
-    let (&id, off) = u16::decode(buf).unwrap();
+    let (id, off) = u16::decode(buf).unwrap();
     let id = Id::try_from(id).unwrap();
     let buf = &buf[off + 1..];
-    let (&data_len, off) = i32::decode(buf).unwrap();
+    let (data_len, off) = i32::decode(buf).unwrap();
     assert_eq!(expected_id as u16, id as u16);
     assert_eq!(expected_data.len() as i32, data_len);
     assert_eq!(expected_data, &buf[off..]);
@@ -16,3 +19,72 @@ fn silence_end() {
     let data = p::silence_end(0);
     packet_test(&data, Id::SilenceEnd, &[0, 0, 0, 0])
 }
+
+#[test]
+fn send_message_round_trip() {
+    let packet = p::SendMessage {
+        from: "peppy",
+        content: "hello",
+        to: "#osu",
+        from_id: 2,
+    };
+    let data = packet.to_packet();
+    assert_eq!(read_packet::<p::SendMessage>(&data).unwrap(), packet);
+    assert!(read_packet::<p::Notification>(&data).is_err());
+}
+
+#[test]
+fn channel_auto_join_round_trip() {
+    let packet = p::ChannelAutoJoin {
+        name: "#osu",
+        desc: "main",
+        users: 3,
+    };
+    let data = packet.to_packet();
+    assert_eq!(read_packet::<p::ChannelAutoJoin>(&data).unwrap(), packet);
+    assert!(read_packet::<p::ChannelInfo>(&data).is_err());
+}
+
+#[test]
+fn empty_packet() {
+    let data = p::match_skip();
+    packet_test(&data, Id::MatchSkip, &[]);
+    assert_eq!(read_packet::<p::MatchSkip>(&data).unwrap(), p::MatchSkip);
+}
+
+#[test]
+fn match_info_round_trip() {
+    let mut slot_statuses = [SlotStatus::Free; 16];
+    slot_statuses[0] = SlotStatus::NotReady;
+    slot_statuses[3] = SlotStatus::Ready;
+    let mut slot_ids = [-1; 16];
+    slot_ids[0] = 10;
+    slot_ids[3] = 12;
+    let info = MatchInfo {
+        id: 1,
+        in_progress: false,
+        match_type: 0,
+        mods: 0,
+        name: "test match",
+        password: "",
+        beatmap_name: "map",
+        beatmap_id: 123,
+        beatmap_md5: "abc",
+        slot_statuses,
+        slot_teams: [Team::NoTeam; 16],
+        slot_ids: SlotIds(slot_ids),
+        host_id: 10,
+        game_mode: 0,
+        scoring_type: ScoringType::Score,
+        team_type: TeamType::HeadToHead,
+        freemod: true,
+        slot_mods: Some([0; 16]),
+        seed: 0,
+    };
+    let data = info.to_packet(Id::UpdateMatch);
+    let (id, data) = packet_data(&data).unwrap();
+    assert_eq!(id as u16, Id::UpdateMatch as u16);
+    let (decoded, read) = MatchInfo::decode(data).unwrap();
+    assert_eq!(read, data.len());
+    assert_eq!(decoded, info);
+}
@@ -0,0 +1,40 @@
+use isoku::{
+    limiter::RateLimiter,
+    spam::{FilterMode, SpamError, SpamFilter},
+};
+use std::{borrow::Cow, time::Duration};
+
+#[test]
+fn censor() {
+    let filter = SpamFilter {
+        words: vec!["cookiezi".to_string(), "żółw".to_string()],
+        ..SpamFilter::default()
+    };
+    assert_eq!(filter.censor("hello"), None);
+    assert_eq!(
+        filter.censor("CookieZi is a żółw!").as_deref(),
+        Some("******** is a ****!")
+    );
+    assert_eq!(filter.censor("ŻÓŁW").as_deref(), Some("****"));
+    assert_eq!(filter.censor("żół"), None);
+}
+
+#[tokio::test]
+async fn check() {
+    let filter = SpamFilter {
+        messages: RateLimiter::new(2, Duration::from_secs(60)),
+        floods: RateLimiter::new(2, Duration::from_secs(60)),
+        max_length: 10,
+        words: vec!["bad".to_string()],
+        mode: FilterMode::Reject,
+        ..SpamFilter::default()
+    };
+    assert_eq!(filter.check(1, "hi").await, Ok(Cow::Borrowed("hi")));
+    assert_eq!(filter.check(1, "very bad").await, Err(SpamError::Filtered));
+    assert_eq!(filter.check(1, "hi").await, Err(SpamError::Flood { silence: false }));
+    assert_eq!(filter.check(1, "hi").await, Err(SpamError::Flood { silence: true }));
+    assert_eq!(
+        filter.check(2, "way too long").await,
+        Err(SpamError::TooLong { max: 10 })
+    );
+}